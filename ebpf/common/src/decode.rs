@@ -0,0 +1,396 @@
+//! Checked decoding of `RINGBUF` records into owned events, for the userland.
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::{self, Display},
+    mem::{align_of, size_of},
+    time::Duration,
+};
+
+use aya::Pod;
+
+use crate::{
+    ExecHeader, ExitHeader, ForkHeader, EVENT_EXEC, EVENT_EXIT, EVENT_FORK, FLAG_ARGS_TRUNCATED,
+    FLAG_ENVS_TRUNCATED, SYSCALL_EXECVEAT,
+};
+
+/// Why a `RINGBUF` record could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The record is shorter than its header, or than the strings the header announces.
+    TooShort { expected: usize, actual: usize },
+    /// The record does not start at an address suitable for its header.
+    ///
+    /// The kernel aligns ring buffer records to 8 bytes, so this hints at a bogus slice.
+    Misaligned { align: usize },
+    /// The first field is not one of the `EVENT_*` kinds.
+    UnknownKind(u32),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TooShort { expected, actual } => {
+                write!(f, "record of {actual} bytes, expected {expected}")
+            }
+            DecodeError::Misaligned { align } => {
+                write!(f, "record not aligned to {align} bytes")
+            }
+            DecodeError::UnknownKind(kind) => write!(f, "unknown record kind {kind}"),
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+/// Anything reported by the kernel.
+#[derive(Debug, Clone)]
+pub enum DecodedEvent {
+    Exec(DecodedExec),
+    Exit(DecodedExit),
+    Fork(DecodedFork),
+}
+
+impl DecodedEvent {
+    /// Decodes a `RINGBUF` record of any kind.
+    pub fn decode(record: &[u8]) -> Result<Self, DecodeError> {
+        let kind: u32 = read_header(record)?;
+        match kind {
+            EVENT_EXEC => DecodedExec::decode(record).map(Self::Exec),
+            EVENT_EXIT => DecodedExit::decode(record).map(Self::Exit),
+            EVENT_FORK => DecodedFork::decode(record).map(Self::Fork),
+            kind => Err(DecodeError::UnknownKind(kind)),
+        }
+    }
+}
+
+impl Display for DecodedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodedEvent::Exec(event) => event.fmt(f),
+            DecodedEvent::Exit(event) => event.fmt(f),
+            DecodedEvent::Fork(event) => event.fmt(f),
+        }
+    }
+}
+
+/// An exec, with the strings following its header decoded.
+#[derive(Debug, Clone)]
+pub struct DecodedExec {
+    pub header: ExecHeader,
+    /// The comm of the task, without NUL padding.
+    pub comm: String,
+    /// The filename as passed to the syscall, relative to [`ExecHeader::dirfd`] unless absolute.
+    pub filename: String,
+    pub args: Vec<String>,
+    pub envs: Vec<String>,
+}
+
+impl DecodedExec {
+    /// Decodes a record of kind [`EVENT_EXEC`], see [`ExecHeader`] for the layout.
+    pub fn decode(record: &[u8]) -> Result<Self, DecodeError> {
+        let header: ExecHeader = read_header(record)?;
+        let data = &record[ExecHeader::SIZE..];
+        let filename_end = header.filename_len as usize;
+        let args_end = filename_end.saturating_add(header.args_len as usize);
+        let envs_end = args_end.saturating_add(header.envs_len as usize);
+        if envs_end > data.len() {
+            return Err(DecodeError::TooShort {
+                expected: ExecHeader::SIZE.saturating_add(envs_end),
+                actual: record.len(),
+            });
+        }
+        Ok(Self {
+            header,
+            comm: decode_comm(&header.comm),
+            filename: decode_string(&data[..filename_end]),
+            args: decode_strings(&data[filename_end..args_end]),
+            envs: decode_strings(&data[args_end..envs_end]),
+        })
+    }
+
+    /// Whether argv had more entries or bytes than the kernel copies.
+    pub fn args_truncated(&self) -> bool {
+        self.header.flags & FLAG_ARGS_TRUNCATED != 0
+    }
+
+    /// Whether envp had more entries or bytes than the kernel copies.
+    pub fn envs_truncated(&self) -> bool {
+        self.header.flags & FLAG_ENVS_TRUNCATED != 0
+    }
+
+    /// The errno the exec failed with, `None` if it succeeded.
+    pub fn errno(&self) -> Option<i32> {
+        (self.header.ret < 0).then_some(-self.header.ret)
+    }
+
+    /// Whether the exec went through `execveat` rather than `execve`.
+    pub fn is_execveat(&self) -> bool {
+        self.header.syscall == SYSCALL_EXECVEAT
+    }
+}
+
+impl Display for DecodedExec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): ", self.comm, self.header.pid)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(arg)?;
+        }
+        if self.args_truncated() {
+            write!(f, " ...")?;
+        }
+        if let Some(errno) = self.errno() {
+            write!(f, " = -{}", errno)?;
+        }
+        Ok(())
+    }
+}
+
+/// The exit of a process previously seen forking or executing.
+#[derive(Debug, Clone)]
+pub struct DecodedExit {
+    pub header: ExitHeader,
+    /// The comm of the task, without NUL padding.
+    pub comm: String,
+}
+
+impl DecodedExit {
+    /// Decodes a record of kind [`EVENT_EXIT`].
+    pub fn decode(record: &[u8]) -> Result<Self, DecodeError> {
+        let header: ExitHeader = read_header(record)?;
+        Ok(Self {
+            header,
+            comm: decode_comm(&header.comm),
+        })
+    }
+
+    /// The exit code, `None` if the process was killed by a signal.
+    pub fn exit_code(&self) -> Option<i32> {
+        (self.signal().is_none()).then_some(((self.header.exit_code >> 8) & 0xff) as i32)
+    }
+
+    /// The signal that terminated the process, if any.
+    pub fn signal(&self) -> Option<i32> {
+        match self.header.exit_code & 0x7f {
+            0 => None,
+            signal => Some(signal as i32),
+        }
+    }
+
+    /// Time between the last exec of the process and its exit.
+    pub fn duration(&self) -> Duration {
+        Duration::from_nanos(self.header.duration)
+    }
+}
+
+impl Display for DecodedExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): ", self.comm, self.header.pid)?;
+        match self.signal() {
+            Some(signal) => write!(f, "killed by signal {}", signal)?,
+            None => write!(f, "exited with {}", self.exit_code().unwrap_or_default())?,
+        }
+        write!(f, " after {:?}", self.duration())
+    }
+}
+
+/// A new process, created by a clone-like syscall.
+#[derive(Debug, Clone)]
+pub struct DecodedFork {
+    pub header: ForkHeader,
+    /// The comm inherited from the parent, without NUL padding.
+    pub comm: String,
+}
+
+impl DecodedFork {
+    /// Decodes a record of kind [`EVENT_FORK`].
+    pub fn decode(record: &[u8]) -> Result<Self, DecodeError> {
+        let header: ForkHeader = read_header(record)?;
+        Ok(Self {
+            header,
+            comm: decode_comm(&header.comm),
+        })
+    }
+}
+
+impl Display for DecodedFork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): forked {} with flags {:#x}",
+            self.comm, self.header.ppid, self.header.pid, self.header.clone_flags
+        )
+    }
+}
+
+/// Copies the header `H` from the start of `record`, after checking its length and alignment.
+fn read_header<H: Pod>(record: &[u8]) -> Result<H, DecodeError> {
+    if record.len() < size_of::<H>() {
+        return Err(DecodeError::TooShort {
+            expected: size_of::<H>(),
+            actual: record.len(),
+        });
+    }
+    if record.as_ptr().align_offset(align_of::<H>()) != 0 {
+        return Err(DecodeError::Misaligned {
+            align: align_of::<H>(),
+        });
+    }
+    // SAFETY: the bytes are in bounds and aligned for `H`, and every bit pattern is a valid `H`
+    // as promised by `Pod`.
+    Ok(unsafe { record.as_ptr().cast::<H>().read() })
+}
+
+/// A fixed size comm, up to the first NUL.
+fn decode_comm(comm: &[u8; 16]) -> String {
+    let len = comm.iter().position(|&b| b == 0).unwrap_or(comm.len());
+    String::from_utf8_lossy(&comm[..len]).into_owned()
+}
+
+/// A string followed by NUL padding, with the trailing NULs stripped.
+fn decode_string(bytes: &[u8]) -> String {
+    let len = bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Consecutive NUL terminated strings, an empty string in between is kept.
+fn decode_strings(bytes: &[u8]) -> Vec<String> {
+    let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+    if bytes.is_empty() {
+        return Vec::new();
+    }
+    bytes
+        .split(|&b| b == 0)
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::AT_FDCWD;
+
+    /// A record made of a header and data, 8 byte aligned like the ring buffer hands them out.
+    struct Record {
+        words: Vec<u64>,
+        len: usize,
+    }
+
+    impl Record {
+        fn new<H: Pod>(header: H, data: &[u8]) -> Self {
+            let len = size_of::<H>() + data.len();
+            let mut words = vec![0u64; len.div_ceil(8)];
+            let ptr = words.as_mut_ptr().cast::<u8>();
+            // SAFETY: the words are aligned to 8 bytes and hold at least `len` bytes.
+            unsafe {
+                ptr.cast::<H>().write(header);
+                ptr.add(size_of::<H>())
+                    .copy_from_nonoverlapping(data.as_ptr(), data.len());
+            }
+            Self { words, len }
+        }
+
+        fn bytes(&self) -> &[u8] {
+            // SAFETY: `len` bytes of the words were written in `new`.
+            unsafe { core::slice::from_raw_parts(self.words.as_ptr().cast(), self.len) }
+        }
+    }
+
+    fn exec_header(filename_len: u32, args_len: u32, envs_len: u32, flags: u32) -> ExecHeader {
+        ExecHeader {
+            kind: EVENT_EXEC,
+            pid: 42,
+            timestamp: 1,
+            start_time: 0,
+            parent_start_time: 0,
+            uid: 1000,
+            gid: 100,
+            ppid: 1,
+            comm: *b"ls\0\0\0\0\0\0\0\0\0\0\0\0\0\0",
+            filename_len,
+            args_len,
+            envs_len,
+            flags,
+            ret: 0,
+            syscall: 0,
+            dirfd: AT_FDCWD,
+            at_flags: 0,
+        }
+    }
+
+    fn exit_header(exit_code: u32) -> ExitHeader {
+        ExitHeader {
+            kind: EVENT_EXIT,
+            pid: 42,
+            timestamp: 2,
+            duration: 1_500_000_000,
+            start_time: 0,
+            exit_code,
+            comm: *b"ls\0\0\0\0\0\0\0\0\0\0\0\0\0\0",
+        }
+    }
+
+    #[test]
+    fn test_decode_exec() {
+        let data = b"/bin/ls\0\0\0ls\0-la\0\0\xff\0HOME=/root\0";
+        let record = Record::new(exec_header(10, 10, 11, FLAG_ARGS_TRUNCATED), data);
+        let Ok(DecodedEvent::Exec(event)) = DecodedEvent::decode(record.bytes()) else {
+            panic!("not an exec");
+        };
+        assert_eq!(event.comm, "ls");
+        assert_eq!(event.filename, "/bin/ls");
+        assert_eq!(event.args, ["ls", "-la", "", "\u{fffd}"]);
+        assert_eq!(event.envs, ["HOME=/root"]);
+        assert!(event.args_truncated());
+        assert!(!event.envs_truncated());
+    }
+
+    #[test]
+    fn test_decode_exit() {
+        let record = Record::new(exit_header(11), &[]); // SIGSEGV
+        let Ok(DecodedEvent::Exit(event)) = DecodedEvent::decode(record.bytes()) else {
+            panic!("not an exit");
+        };
+        assert_eq!(event.signal(), Some(11));
+        assert_eq!(event.exit_code(), None);
+        assert_eq!(event.duration(), Duration::from_millis(1500));
+
+        let event = DecodedExit::decode(Record::new(exit_header(3 << 8), &[]).bytes()).unwrap();
+        assert_eq!(event.exit_code(), Some(3));
+        assert_eq!(event.signal(), None);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(
+            DecodedEvent::decode(&[0; 2]).unwrap_err(),
+            DecodeError::TooShort {
+                expected: 4,
+                actual: 2
+            }
+        );
+        let record = Record::new(exec_header(0, 8, 11, 0), b"ls\0");
+        assert_eq!(
+            DecodedEvent::decode(record.bytes()).unwrap_err(),
+            DecodeError::TooShort {
+                expected: ExecHeader::SIZE + 19,
+                actual: ExecHeader::SIZE + 3
+            }
+        );
+        let record = Record::new(exit_header(0), &[0; 8]);
+        assert_eq!(
+            DecodedExit::decode(&record.bytes()[4..]).unwrap_err(),
+            DecodeError::Misaligned { align: 8 }
+        );
+        let record = Record::new(7u32, &[]);
+        assert_eq!(
+            DecodedEvent::decode(record.bytes()).unwrap_err(),
+            DecodeError::UnknownKind(7)
+        );
+    }
+}
@@ -1,44 +1,179 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
-use core::fmt::{self, Formatter};
+#[cfg(feature = "user")]
+extern crate alloc;
 
+#[cfg(feature = "user")]
+mod decode;
+#[cfg(feature = "user")]
+pub use decode::{DecodeError, DecodedEvent, DecodedExec, DecodedExit, DecodedFork};
 
+/// Upper bound on the bytes of the executed filename, NUL terminator included.
 pub const MAX_PATH_LEN: usize = 512;
 
+/// Upper bound on the bytes of argv strings, NUL terminators included, copied per exec.
+pub const MAX_ARGS_SIZE: usize = 4096;
+/// Upper bound on the number of argv entries copied per exec.
+pub const MAX_ARGS: usize = 128;
 
-pub const ARG_SIZE: usize = 64;
-pub const ARG_COUNT: usize = 20;
+/// Upper bound on the bytes of envp strings, NUL terminators included, copied per exec.
+pub const MAX_ENVS_SIZE: usize = 2048;
+/// Upper bound on the number of envp entries copied per exec.
+pub const MAX_ENVS: usize = 64;
 
+/// Set in [`ExecHeader::flags`] when argv did not fit into [`MAX_ARGS_SIZE`] / [`MAX_ARGS`].
+pub const FLAG_ARGS_TRUNCATED: u32 = 1 << 0;
+/// Set in [`ExecHeader::flags`] when envp did not fit into [`MAX_ENVS_SIZE`] / [`MAX_ENVS`].
+pub const FLAG_ENVS_TRUNCATED: u32 = 1 << 1;
 
-pub const ENV_SIZE: usize = 100;
-pub const ENV_COUNT: usize = 20;
+/// Kind of a record in `RINGBUF`, stored in the first field of every record.
+///
+/// An [`ExecHeader`] followed by its strings.
+pub const EVENT_EXEC: u32 = 0;
+/// An [`ExitHeader`].
+pub const EVENT_EXIT: u32 = 1;
+/// A [`ForkHeader`].
+pub const EVENT_FORK: u32 = 2;
 
+/// Set in [`ForkHeader::clone_flags`] if the child shares the memory of the parent.
+pub const CLONE_VM: u64 = 0x100;
+/// Set in [`ForkHeader::clone_flags`] if the parent is suspended until the child execs or exits.
+pub const CLONE_VFORK: u64 = 0x4000;
+/// Clone flag of new threads, which are not reported as forks.
+pub const CLONE_THREAD: u64 = 0x10000;
+
+/// [`ExecHeader::syscall`] of an exec through `execve`.
+pub const SYSCALL_EXECVE: u32 = 0;
+/// [`ExecHeader::syscall`] of an exec through `execveat`.
+pub const SYSCALL_EXECVEAT: u32 = 1;
+
+/// [`ExecHeader::dirfd`] meaning the filename is relative to the working directory.
+pub const AT_FDCWD: i32 = -100;
+/// Set in [`ExecHeader::at_flags`] to execute `dirfd` itself when the filename is empty.
+pub const AT_EMPTY_PATH: u32 = 0x1000;
+
+/// Value in the `UID_FILTER` and `COMM_FILTER` maps of an entry to trace exclusively.
+pub const FILTER_INCLUDE: u8 = 1;
+/// Value in the `UID_FILTER` and `COMM_FILTER` maps of an entry to ignore.
+pub const FILTER_EXCLUDE: u8 = 2;
+
+/// Set in `FILTER_FLAGS` when `UID_FILTER` has included uids, so other uids are ignored.
+pub const FILTER_UID_INCLUDE: u32 = 1 << 0;
+/// Set in `FILTER_FLAGS` when `COMM_FILTER` has included comms, so other comms are ignored.
+pub const FILTER_COMM_INCLUDE: u32 = 1 << 1;
+/// Set in `FILTER_FLAGS` to only trace the processes in `TARGET_PIDS`.
+pub const FILTER_PID: u32 = 1 << 2;
+
+/// Upper bound on the entries of each filter map.
+pub const MAX_FILTER_ENTRIES: u32 = 256;
+
+/// Upper bound on execs that have entered but not yet returned, across all tasks.
+pub const MAX_INFLIGHT_EXECS: u32 = 1024;
+
+/// Upper bound on processes whose start time is remembered until they exit.
+pub const MAX_TRACKED_PROCESSES: u32 = 16384;
+
+/// Default size of `RINGBUF` in bytes, room for a few hundred events.
+///
+/// The userland can override it at load time; the kernel requires a power of two that is a
+/// multiple of the page size.
+pub const RING_BUF_SIZE: u32 = 1 << 20;
+
+/// Fixed size part of an exec record in `RINGBUF`.
+///
+/// A record is laid out as the header, followed by the NUL terminated filename in
+/// `filename_len` bytes, followed by `args_len` bytes of NUL terminated argv strings, followed
+/// by `envs_len` bytes of NUL terminated envp strings. Only the bytes actually used are
+/// submitted, so the record size varies with the command line.
+#[repr(C)]
 #[derive(Debug, Clone, Copy)]
-pub struct Event {
-    pub timestamp: u64, // nanoseconds since boot
+pub struct ExecHeader {
+    /// Always [`EVENT_EXEC`].
+    pub kind: u32,
+    pub pid: u32,
+    pub timestamp: u64, // nanoseconds of CLOCK_BOOTTIME, which keeps counting during suspend
+    /// When the process started, in nanoseconds of CLOCK_BOOTTIME. Together with the pid it tells
+    /// the process apart from later ones reusing the pid. 0 if it could not be read.
+    pub start_time: u64,
+    /// When the parent process started, like `start_time`.
+    pub parent_start_time: u64,
     pub uid: u32,
     pub gid: u32,
+    pub ppid: u32,
+    pub comm: [u8; 16],
+    /// 0 if the filename could not be read.
+    pub filename_len: u32,
+    pub args_len: u32,
+    pub envs_len: u32,
+    pub flags: u32,
+    /// Return value of the exec: 0 on success, a negative errno on failure.
+    pub ret: i32,
+    /// [`SYSCALL_EXECVE`] or [`SYSCALL_EXECVEAT`].
+    pub syscall: u32,
+    /// Directory a relative filename is resolved against, [`AT_FDCWD`] for `execve`.
+    pub dirfd: i32,
+    /// The `AT_*` flags passed to `execveat`, 0 for `execve`.
+    pub at_flags: u32,
+}
+
+impl ExecHeader {
+    /// Size of the header, i.e. the offset of the filename in a record.
+    pub const SIZE: usize = core::mem::size_of::<Self>();
+}
+
+/// Record in `RINGBUF` for a process that exited after being seen forking or executing.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExitHeader {
+    /// Always [`EVENT_EXIT`].
+    pub kind: u32,
+    pub pid: u32,
+    pub timestamp: u64, // nanoseconds of CLOCK_BOOTTIME
+    /// Nanoseconds between the last successful exec of the process, or its fork if it did not
+    /// exec since, and its exit.
+    pub duration: u64,
+    /// When the process started, like [`ExecHeader::start_time`].
+    pub start_time: u64,
+    /// The status as reported by `wait`, from `task_struct::exit_code`: the exit code in bits 8
+    /// to 15, or the terminating signal in bits 0 to 6.
+    pub exit_code: u32,
+    pub comm: [u8; 16],
+}
+
+impl ExitHeader {
+    /// Size of the record.
+    pub const SIZE: usize = core::mem::size_of::<Self>();
+}
+
+/// Record in `RINGBUF` for a new process created by a clone-like syscall.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ForkHeader {
+    /// Always [`EVENT_FORK`].
+    pub kind: u32,
+    /// Pid of the child.
     pub pid: u32,
+    pub timestamp: u64, // nanoseconds of CLOCK_BOOTTIME
+    /// The `CLONE_*` flags of the syscall, including the exit signal in the lowest byte.
+    pub clone_flags: u64,
+    /// When the parent started, like [`ExecHeader::start_time`]. The one of the child is not
+    /// known to the tracepoint, it shows with the first exec of the child.
+    pub parent_start_time: u64,
+    /// Pid of the parent.
     pub ppid: u32,
+    /// The comm of the parent, which the child inherits.
     pub comm: [u8; 16],
-    pub args: [[u8; ARG_SIZE]; ARG_COUNT],
-    pub envs: [[u8; ENV_SIZE]; ENV_COUNT],
 }
 
-impl core::fmt::Display for Event {
-    // include envs and args
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let comm = core::str::from_utf8(&self.comm).unwrap_or_default();
-        write!(f, "{} ({}): ", comm, self.pid)?;
-        for arg in &self.args {
-            let arg = core::str::from_utf8(arg).unwrap_or_default();
-            write!(f, "{} ", arg)?;
-        }
-        for env in &self.envs {
-            let env = core::str::from_utf8(env).unwrap_or_default();
-            write!(f, "{} ", env)?;
-        }
-        Ok(())
-    }
+impl ForkHeader {
+    /// Size of the record.
+    pub const SIZE: usize = core::mem::size_of::<Self>();
 }
 
+// SAFETY: plain integers and byte arrays, any bit pattern is valid.
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for ExecHeader {}
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for ExitHeader {}
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for ForkHeader {}
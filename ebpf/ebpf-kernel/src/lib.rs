@@ -5,6 +5,6 @@
 #[allow(non_camel_case_types)]
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
+#[allow(unnecessary_transmutes)]
 #[rustfmt::skip]
 mod vmlinux;
-
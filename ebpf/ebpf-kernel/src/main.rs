@@ -6,20 +6,28 @@
 #[allow(non_camel_case_types)]
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
+#[allow(unnecessary_transmutes)]
 #[rustfmt::skip]
 mod vmlinux;
 
 use aya_ebpf::{
+    cty::c_void,
     helpers::{
         bpf_get_current_comm, bpf_get_current_pid_tgid, bpf_get_current_task_btf,
-        bpf_get_current_uid_gid, bpf_ktime_get_ns, bpf_probe_read_user,
-        bpf_probe_read_user_str_bytes,
+        bpf_get_current_uid_gid, bpf_ktime_get_boot_ns, bpf_probe_read_kernel, bpf_probe_read_user,
+        gen,
     },
     macros::{map, tracepoint},
-    maps::{PerCpuArray, RingBuf},
+    maps::{Array, HashMap, LruHashMap, PerCpuArray, RingBuf},
     programs::TracePointContext,
 };
-use ebpf_common::{Event, ARG_COUNT, ARG_SIZE, ENV_COUNT};
+use ebpf_common::{
+    ExecHeader, ExitHeader, ForkHeader, AT_FDCWD, CLONE_THREAD, CLONE_VFORK, CLONE_VM, EVENT_EXEC,
+    EVENT_EXIT, EVENT_FORK, FILTER_COMM_INCLUDE, FILTER_EXCLUDE, FILTER_PID, FILTER_UID_INCLUDE,
+    FLAG_ARGS_TRUNCATED, FLAG_ENVS_TRUNCATED, MAX_ARGS, MAX_ARGS_SIZE, MAX_ENVS, MAX_ENVS_SIZE,
+    MAX_FILTER_ENTRIES, MAX_INFLIGHT_EXECS, MAX_PATH_LEN, MAX_TRACKED_PROCESSES, RING_BUF_SIZE,
+    SYSCALL_EXECVE, SYSCALL_EXECVEAT,
+};
 
 #[repr(C)]
 pub struct SysEnterExecve {
@@ -36,6 +44,90 @@ pub struct SysEnterExecve {
     pub envp: *const *const u8,
 }
 
+#[repr(C)]
+pub struct SysEnterClone {
+    // Tracepoint header fields.
+    pub common_type: u16,
+    pub common_flags: u8,
+    pub common_preempt_count: u8,
+    pub common_pid: i32,
+    // Additional syscall-specific field.
+    pub __syscall_nr: i32,
+    // Clone-specific fields:
+    pub clone_flags: u64,
+    pub newsp: u64,
+    pub parent_tidptr: *const i32,
+    pub child_tidptr: *const i32,
+    pub tls: u64,
+}
+
+#[repr(C)]
+pub struct SysEnterClone3 {
+    // Tracepoint header fields.
+    pub common_type: u16,
+    pub common_flags: u8,
+    pub common_preempt_count: u8,
+    pub common_pid: i32,
+    // Additional syscall-specific field.
+    pub __syscall_nr: i32,
+    // Clone3-specific fields, `flags` is the first member of `struct clone_args`:
+    pub uargs: *const u64,
+    pub size: u64,
+}
+
+#[repr(C)]
+pub struct SysEnterExecveat {
+    // Tracepoint header fields.
+    pub common_type: u16,
+    pub common_flags: u8,
+    pub common_preempt_count: u8,
+    pub common_pid: i32,
+    // Additional syscall-specific field.
+    pub __syscall_nr: i32,
+    // Execveat-specific fields, the ints are widened to 8 bytes by the tracepoint:
+    pub fd: i64,
+    pub filename: *const u8,
+    pub argv: *const *const u8,
+    pub envp: *const *const u8,
+    pub flags: i64,
+}
+
+/// Shared by `sys_exit_execve` and `sys_exit_execveat`, which have the same layout.
+#[repr(C)]
+pub struct SysExitExecve {
+    // Tracepoint header fields.
+    pub common_type: u16,
+    pub common_flags: u8,
+    pub common_preempt_count: u8,
+    pub common_pid: i32,
+    // Additional syscall-specific field.
+    pub __syscall_nr: i32,
+    pub ret: i64,
+}
+
+/// Room for the strings of one record.
+///
+/// The verifier only tracks the range of each offset and length separately, so every string
+/// read must fit even when started at the largest possible offset with the largest possible
+/// size. Hence, after the filename, twice the argv budget, and the envp budget twice on top of
+/// the argv budget.
+const DATA_SIZE: usize = MAX_PATH_LEN + max(2 * MAX_ARGS_SIZE, MAX_ARGS_SIZE + 2 * MAX_ENVS_SIZE);
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Per-CPU scratch space a record is assembled in before being copied to `RINGBUF`.
+#[repr(C)]
+pub struct ExecRecord {
+    header: ExecHeader,
+    data: [u8; DATA_SIZE],
+}
+
 #[tracepoint(name = "sys_enter_execve", category = "syscalls")]
 pub fn sys_enter_execve(ctx: TracePointContext) -> u32 {
     match try_enter_execve(ctx) {
@@ -44,22 +136,196 @@ pub fn sys_enter_execve(ctx: TracePointContext) -> u32 {
     }
 }
 
+#[tracepoint(name = "sys_exit_execve", category = "syscalls")]
+pub fn sys_exit_execve(ctx: TracePointContext) -> u32 {
+    match try_exit_execve(ctx) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+#[tracepoint(name = "sys_enter_execveat", category = "syscalls")]
+pub fn sys_enter_execveat(ctx: TracePointContext) -> u32 {
+    match try_enter_execveat(ctx) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+#[tracepoint(name = "sys_exit_execveat", category = "syscalls")]
+pub fn sys_exit_execveat(ctx: TracePointContext) -> u32 {
+    match try_exit_execve(ctx) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+#[tracepoint(name = "sched_process_exit", category = "sched")]
+pub fn sched_process_exit(ctx: TracePointContext) -> u32 {
+    match try_process_exit(ctx) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+#[tracepoint(name = "sys_enter_clone", category = "syscalls")]
+pub fn sys_enter_clone(ctx: TracePointContext) -> u32 {
+    match try_enter_clone(ctx) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+#[tracepoint(name = "sys_enter_clone3", category = "syscalls")]
+pub fn sys_enter_clone3(ctx: TracePointContext) -> u32 {
+    match try_enter_clone3(ctx) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+#[tracepoint(name = "sys_enter_fork", category = "syscalls")]
+pub fn sys_enter_fork(_ctx: TracePointContext) -> u32 {
+    match set_clone_flags(SIGCHLD) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+#[tracepoint(name = "sys_enter_vfork", category = "syscalls")]
+pub fn sys_enter_vfork(_ctx: TracePointContext) -> u32 {
+    match set_clone_flags(CLONE_VFORK | CLONE_VM | SIGCHLD) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+#[tracepoint(name = "sched_process_fork", category = "sched")]
+pub fn sched_process_fork(ctx: TracePointContext) -> u32 {
+    match try_process_fork(ctx) {
+        Ok(ret) => ret,
+        Err(ret) => ret as u32,
+    }
+}
+
+/// Offset of `child_pid` in the `sched_process_fork` record.
+///
+/// It moved when the comm fields of the tracepoint became dynamic arrays, so the userland sets
+/// it from the tracepoint format before loading. The default is the offset of older kernels.
+#[no_mangle]
+static FORK_CHILD_PID_OFFSET: u32 = 44;
+
+/// Exit signal of `fork` and `vfork`, part of their implied clone flags.
+const SIGCHLD: u64 = 17;
+
+#[map]
+pub static BUF: PerCpuArray<ExecRecord> = PerCpuArray::with_max_entries(1, 0);
+
+/// Records of execs that entered the syscall but have not returned yet, keyed by tid.
+///
+/// An LRU map, so entries of tasks that never return (killed mid exec, or a non-leader thread
+/// whose tid changed on success) are eventually evicted instead of filling the map.
+#[map]
+static INFLIGHT: LruHashMap<u32, ExecRecord> = LruHashMap::with_max_entries(MAX_INFLIGHT_EXECS, 0);
+
+/// Time each process started running its current program, keyed by pid: the last successful
+/// exec, or the fork if it did not exec since.
+///
+/// Doubles as the set of processes whose exit is reported, so that exits of processes the
+/// userland never saw starting do not fill `RINGBUF`.
+#[map]
+static START_TIMES: LruHashMap<u32, u64> = LruHashMap::with_max_entries(MAX_TRACKED_PROCESSES, 0);
+
+/// Flags of clone-like syscalls in progress, keyed by the tid of the caller, until
+/// `sched_process_fork` reports the new child.
+#[map]
+static CLONE_FLAGS: LruHashMap<u32, u64> = LruHashMap::with_max_entries(MAX_INFLIGHT_EXECS, 0);
+
+/// The `FILTER_*` flags telling which of the filter maps are in effect, at index 0.
 #[map]
-pub static BUF: PerCpuArray<Event> = PerCpuArray::with_max_entries(1, 0);
+static FILTER_FLAGS: Array<u32> = Array::with_max_entries(1, 0);
 
+/// `FILTER_INCLUDE` or `FILTER_EXCLUDE` per uid.
+#[map]
+static UID_FILTER: HashMap<u32, u8> = HashMap::with_max_entries(MAX_FILTER_ENTRIES, 0);
+
+/// `FILTER_INCLUDE` or `FILTER_EXCLUDE` per comm, NUL padded like `bpf_get_current_comm`.
+#[map]
+static COMM_FILTER: HashMap<[u8; 16], u8> = HashMap::with_max_entries(MAX_FILTER_ENTRIES, 0);
+
+/// The target pid set by the userland, and its descendants forked since, when `FILTER_PID` is
+/// in effect.
+#[map]
+static TARGET_PIDS: LruHashMap<u32, u8> = LruHashMap::with_max_entries(MAX_TRACKED_PROCESSES, 0);
+
+// The size is only a default, the userland sets the real one before loading.
 #[map(name = "RINGBUF")]
-static mut RINGBUF: RingBuf = RingBuf::with_byte_size(256, 0);
+static RINGBUF: RingBuf = RingBuf::with_byte_size(RING_BUF_SIZE, 0);
+
+/// Number of events that could not be submitted because `RINGBUF` was full, per CPU.
+#[map(name = "DROPPED")]
+static DROPPED: PerCpuArray<u64> = PerCpuArray::with_max_entries(1, 0);
 
-// Implemention based on the suspection from here: https://github.com/notashes/syspection/blob/e5756aec507c2a9097331393b534392412c63d9b/syspection-ebpf/src/main.rs#L70
 fn try_enter_execve(ctx: TracePointContext) -> Result<u32, i64> {
+    let data: SysEnterExecve = unsafe { ctx.read_at(0).map_err(|_| -1)? };
+    // `execve` behaves like `execveat` relative to the working directory without flags.
+    record_exec(
+        SYSCALL_EXECVE,
+        AT_FDCWD,
+        0,
+        data.filename,
+        data.argv,
+        data.envp,
+    )
+}
+
+fn try_enter_execveat(ctx: TracePointContext) -> Result<u32, i64> {
+    let data: SysEnterExecveat = unsafe { ctx.read_at(0).map_err(|_| -1)? };
+    record_exec(
+        SYSCALL_EXECVEAT,
+        data.fd as i32,
+        data.flags as u32,
+        data.filename,
+        data.argv,
+        data.envp,
+    )
+}
+
+/// Assembles the record of an exec that just entered the kernel and parks it in `INFLIGHT`
+/// until the exit tracepoint knows its return value.
+// Implemention based on the suspection from here: https://github.com/notashes/syspection/blob/e5756aec507c2a9097331393b534392412c63d9b/syspection-ebpf/src/main.rs#L70
+#[inline(always)]
+fn record_exec(
+    syscall: u32,
+    dirfd: i32,
+    at_flags: u32,
+    filename: *const u8,
+    argv: *const *const u8,
+    envp: *const *const u8,
+) -> Result<u32, i64> {
     // Get process info.
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let pid = (pid_tgid >> 32) as u32;
+    let tid = pid_tgid as u32;
     let uid = bpf_get_current_uid_gid() as u32;
     let gid = (bpf_get_current_uid_gid() >> 32) as u32;
     let task = unsafe { bpf_get_current_task_btf() as *const vmlinux::task_struct };
-    let real_parent = unsafe { (*task).real_parent };
-    let ppid = unsafe { (*real_parent).pid } as u32;
-    let timestamp = unsafe { bpf_ktime_get_ns() };
+    // The task struct is not a BTF-typed pointer on the Rust side, so every field has to be
+    // read through the probe helpers; dereferencing it directly is rejected by the verifier.
+    // The offsets come from `vmlinux.rs`, which may not match the running kernel, so a failed
+    // read leaves ppid at 0 instead of dropping the event.
+    let real_parent = unsafe { bpf_probe_read_kernel(core::ptr::addr_of!((*task).real_parent)) };
+    // The tgid, the parent may have forked from any of its threads.
+    let ppid = real_parent
+        .and_then(|real_parent| unsafe {
+            bpf_probe_read_kernel(core::ptr::addr_of!((*real_parent).tgid))
+        })
+        .unwrap_or_default() as u32;
+    let start_time = unsafe { process_start_time(task) };
+    let parent_start_time = real_parent
+        .map(|real_parent| unsafe { process_start_time(real_parent) })
+        .unwrap_or_default();
+    let timestamp = unsafe { bpf_ktime_get_boot_ns() };
 
     // Get the comm (process name).
     let comm = match bpf_get_current_comm() {
@@ -67,61 +333,303 @@ fn try_enter_execve(ctx: TracePointContext) -> Result<u32, i64> {
         Err(ret) => return Err(ret),
     };
 
-    // Read the tracepoint data into our SysEnterExecve struct.
-    let data: SysEnterExecve = unsafe { ctx.read_at(0).map_err(|_| -1)? };
+    // Filter before copying any strings, that is where the time goes.
+    if !is_traced(pid, uid, &comm) {
+        return Ok(0);
+    }
 
-    let event_ref = unsafe {
+    let record = unsafe {
         let ptr = BUF.get_ptr_mut(0).ok_or(0)?;
         &mut *ptr
     };
 
-    let argv = data.argv;
-    for i in 0..ARG_COUNT {
-        let arg_ptr = unsafe { bpf_probe_read_user(argv.offset(i as isize)) }?;
+    let mut flags = 0;
 
-        if arg_ptr.is_null() {
-            break;
-        }
+    // The path as passed by the caller, possibly relative to `dirfd`. The userland resolves it,
+    // the kernel has no cheap way to turn a dentry into a path from a tracepoint.
+    let filename_len = unsafe {
+        gen::bpf_probe_read_user_str(
+            record.data.as_mut_ptr() as *mut c_void,
+            MAX_PATH_LEN as u32,
+            filename as *const c_void,
+        )
+    };
+    let filename_len = if filename_len > 0 {
+        filename_len as usize
+    } else {
+        0
+    };
+    // Re-check the bound so that the verifier knows where the argv strings may start.
+    if filename_len > MAX_PATH_LEN {
+        return Err(-1);
+    }
+    let data = unsafe { record.data.as_mut_ptr().add(filename_len) };
 
-        unsafe {
-            bpf_probe_read_user_str_bytes(arg_ptr, &mut event_ref.args[i as usize])
-                .unwrap_or_default()
-        };
+    let (args_len, truncated) = unsafe { read_strings::<MAX_ARGS, MAX_ARGS_SIZE>(argv, data) };
+    if truncated {
+        flags |= FLAG_ARGS_TRUNCATED;
+    }
+    // Same for the envp strings.
+    if args_len > MAX_ARGS_SIZE {
+        return Err(-1);
     }
 
-    let envp = data.envp;
-    for env in 0..ENV_COUNT {
-        let env_ptr = unsafe { bpf_probe_read_user(envp.offset(env as isize)) }?;
+    let (envs_len, truncated) =
+        unsafe { read_strings::<MAX_ENVS, MAX_ENVS_SIZE>(envp, data.add(args_len)) };
+    if truncated {
+        flags |= FLAG_ENVS_TRUNCATED;
+    }
 
-        if env_ptr.is_null() {
-            break;
-        }
+    record.header = ExecHeader {
+        kind: EVENT_EXEC,
+        pid,
+        timestamp,
+        start_time,
+        parent_start_time,
+        uid,
+        gid,
+        ppid,
+        comm,
+        filename_len: filename_len as u32,
+        args_len: args_len as u32,
+        envs_len: envs_len as u32,
+        flags,
+        ret: 0,
+        syscall,
+        dirfd,
+        at_flags,
+    };
 
-        unsafe {
-            bpf_probe_read_user_str_bytes(env_ptr, &mut event_ref.envs[env as usize])
-                .unwrap_or_default()
-        };
+    // The record is only submitted once the return value is known.
+    INFLIGHT.insert(&tid, record, 0)?;
+
+    Ok(0)
+}
+
+fn try_exit_execve(ctx: TracePointContext) -> Result<u32, i64> {
+    let tid = bpf_get_current_pid_tgid() as u32;
+    let data: SysExitExecve = unsafe { ctx.read_at(0).map_err(|_| -1)? };
+
+    let record = match INFLIGHT.get_ptr_mut(&tid) {
+        Some(ptr) => unsafe { &mut *ptr },
+        None => return Ok(0),
+    };
+    record.header.ret = data.ret as i32;
+    let len = ExecHeader::SIZE
+        + record.header.filename_len as usize
+        + record.header.args_len as usize
+        + record.header.envs_len as usize;
+    submit(record, len);
+
+    if record.header.ret == 0 {
+        START_TIMES.insert(&record.header.pid, &record.header.timestamp, 0)?;
     }
+    INFLIGHT.remove(&tid)?;
 
-    event_ref.timestamp = timestamp;
-    event_ref.uid = uid;
-    event_ref.gid = gid;
-    event_ref.pid = pid;
-    event_ref.ppid = ppid;
-    event_ref.comm = comm;
+    Ok(0)
+}
 
-    unsafe {
-        submit(event_ref);
+fn try_process_exit(_ctx: TracePointContext) -> Result<u32, i64> {
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let pid = (pid_tgid >> 32) as u32;
+    let tid = pid_tgid as u32;
+    // Other threads exiting do not end the process.
+    if pid != tid {
+        return Ok(0);
+    }
+    // The pid may be reused by an unrelated process.
+    let _ = TARGET_PIDS.remove(&pid);
+    let running_since = match unsafe { START_TIMES.get(&pid) } {
+        Some(running_since) => *running_since,
+        None => return Ok(0),
+    };
+    START_TIMES.remove(&pid)?;
+
+    // The current task is the group leader, its `exit_code` is set before this tracepoint: the
+    // wait status of the whole group once it exits.
+    let task = unsafe { bpf_get_current_task_btf() as *const vmlinux::task_struct };
+    let exit_code = unsafe { bpf_probe_read_kernel(core::ptr::addr_of!((*task).exit_code)) }
+        .unwrap_or_default() as u32;
+
+    let timestamp = unsafe { bpf_ktime_get_boot_ns() };
+
+    let event = ExitHeader {
+        kind: EVENT_EXIT,
+        pid,
+        timestamp,
+        duration: timestamp.saturating_sub(running_since),
+        start_time: unsafe { process_start_time(task) },
+        exit_code,
+        comm: bpf_get_current_comm()?,
+    };
+    if RINGBUF.output(&event, 0).is_err() {
+        count_dropped();
+    }
+
+    Ok(0)
+}
+
+fn try_enter_clone(ctx: TracePointContext) -> Result<u32, i64> {
+    let data: SysEnterClone = unsafe { ctx.read_at(0).map_err(|_| -1)? };
+    set_clone_flags(data.clone_flags)
+}
+
+fn try_enter_clone3(ctx: TracePointContext) -> Result<u32, i64> {
+    let data: SysEnterClone3 = unsafe { ctx.read_at(0).map_err(|_| -1)? };
+    let flags = unsafe { bpf_probe_read_user(data.uargs)? };
+    set_clone_flags(flags)
+}
+
+#[inline(always)]
+fn set_clone_flags(flags: u64) -> Result<u32, i64> {
+    let tid = bpf_get_current_pid_tgid() as u32;
+    CLONE_FLAGS.insert(&tid, &flags, 0)?;
+
+    Ok(0)
+}
+
+fn try_process_fork(ctx: TracePointContext) -> Result<u32, i64> {
+    // The tracepoint fires in the context of the parent.
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let ppid = (pid_tgid >> 32) as u32;
+    let tid = pid_tgid as u32;
+    // Tasks the kernel creates on its own, like kernel threads and io_uring workers, do not go
+    // through a clone syscall and are left out.
+    let clone_flags = match unsafe { CLONE_FLAGS.get(&tid) } {
+        Some(clone_flags) => *clone_flags,
+        None => return Ok(0),
+    };
+    CLONE_FLAGS.remove(&tid)?;
+    // New threads are not new processes.
+    if clone_flags & CLONE_THREAD != 0 {
+        return Ok(0);
+    }
+
+    let offset = unsafe { core::ptr::read_volatile(&FORK_CHILD_PID_OFFSET) };
+    let pid: i32 = unsafe { ctx.read_at(offset as usize).map_err(|_| -1)? };
+    let pid = pid as u32;
+    let timestamp = unsafe { bpf_ktime_get_boot_ns() };
+    START_TIMES.insert(&pid, &timestamp, 0)?;
+    if unsafe { TARGET_PIDS.get(&ppid) }.is_some() {
+        TARGET_PIDS.insert(&pid, &1, 0)?;
+    }
+
+    let event = ForkHeader {
+        kind: EVENT_FORK,
+        pid,
+        timestamp,
+        clone_flags,
+        parent_start_time: unsafe {
+            process_start_time(bpf_get_current_task_btf() as *const vmlinux::task_struct)
+        },
+        ppid,
+        comm: bpf_get_current_comm()?,
+    };
+    if RINGBUF.output(&event, 0).is_err() {
+        count_dropped();
     }
 
     Ok(0)
 }
 
-#[inline]
-unsafe fn submit(event: &mut Event) {
-    if let Some(mut buf) = RINGBUF.reserve::<Event>(0) {
-        buf.write(*event);
-        buf.submit(0);
+/// `start_boottime` of the process `task` is a thread of, 0 if it could not be read.
+///
+/// Threads have a start time of their own, the one of the group leader is the one `/proc/<pid>`
+/// reports and the one an exec from another thread inherits.
+#[inline(always)]
+unsafe fn process_start_time(task: *const vmlinux::task_struct) -> u64 {
+    bpf_probe_read_kernel(core::ptr::addr_of!((*task).group_leader))
+        .and_then(|leader| bpf_probe_read_kernel(core::ptr::addr_of!((*leader).start_boottime)))
+        .unwrap_or_default()
+}
+
+/// Whether the filters set by the userland let an exec by this task through.
+#[inline(always)]
+fn is_traced(pid: u32, uid: u32, comm: &[u8; 16]) -> bool {
+    let flags = FILTER_FLAGS.get(0).copied().unwrap_or_default();
+    if flags & FILTER_PID != 0 && unsafe { TARGET_PIDS.get(&pid) }.is_none() {
+        return false;
+    }
+    passes(
+        unsafe { UID_FILTER.get(&uid) },
+        flags & FILTER_UID_INCLUDE != 0,
+    ) && passes(
+        unsafe { COMM_FILTER.get(comm) },
+        flags & FILTER_COMM_INCLUDE != 0,
+    )
+}
+
+/// Applies the entry of a filter map: excluded never passes, included always does, and
+/// anything else only if the filter has no included entries.
+#[inline(always)]
+fn passes(entry: Option<&u8>, include_only: bool) -> bool {
+    match entry {
+        Some(&FILTER_EXCLUDE) => false,
+        Some(_) => true,
+        None => !include_only,
+    }
+}
+
+/// Copies the NULL terminated array of user space strings at `ptrs` to `dst` as consecutive NUL
+/// terminated strings, using at most `BUDGET` bytes and `MAX_COUNT` entries.
+///
+/// Returns the number of bytes written and whether anything had to be left out. `dst` must be
+/// valid for `2 * BUDGET` bytes, see [`DATA_SIZE`].
+#[inline(always)]
+unsafe fn read_strings<const MAX_COUNT: usize, const BUDGET: usize>(
+    ptrs: *const *const u8,
+    dst: *mut u8,
+) -> (usize, bool) {
+    let mut len = 0;
+    for i in 0..MAX_COUNT {
+        let ptr = match bpf_probe_read_user(ptrs.add(i)) {
+            Ok(ptr) => ptr,
+            Err(_) => return (len, true),
+        };
+        if ptr.is_null() {
+            return (len, false);
+        }
+        if len >= BUDGET {
+            return (len, true);
+        }
+        let remaining = BUDGET - len;
+        let ret = gen::bpf_probe_read_user_str(
+            dst.add(len) as *mut c_void,
+            remaining as u32,
+            ptr as *const c_void,
+        );
+        if ret <= 0 {
+            return (len, true);
+        }
+        let ret = ret as usize;
+        len += ret;
+        // A string that filled the rest of the budget was most likely cut short.
+        if ret >= remaining {
+            return (len, true);
+        }
+    }
+    // All entries were copied, it was only complete if the array ends right here.
+    let complete = matches!(bpf_probe_read_user(ptrs.add(MAX_COUNT)), Ok(ptr) if ptr.is_null());
+    (len, !complete)
+}
+
+#[inline(always)]
+fn submit(record: &ExecRecord, len: usize) {
+    if len > core::mem::size_of::<ExecRecord>() {
+        return;
+    }
+    let bytes =
+        unsafe { core::slice::from_raw_parts(record as *const ExecRecord as *const u8, len) };
+    if RINGBUF.output(bytes, 0).is_err() {
+        count_dropped();
+    }
+}
+
+#[inline(always)]
+fn count_dropped() {
+    if let Some(dropped) = DROPPED.get_ptr_mut(0) {
+        // Per-CPU slot, so no other program can race with this increment.
+        unsafe { *dropped += 1 };
     }
 }
 
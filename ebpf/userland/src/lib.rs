@@ -0,0 +1,499 @@
+use std::{
+    os::fd::{AsRawFd, RawFd},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use aya::{
+    maps::{Array, HashMap, MapData, PerCpuArray, RingBuf},
+    programs::TracePoint,
+    Ebpf, EbpfLoader,
+};
+pub use ebpf_common::{DecodeError, DecodedEvent, DecodedExec, DecodedExit, DecodedFork};
+use ebpf_common::{
+    AT_EMPTY_PATH, AT_FDCWD, FILTER_COMM_INCLUDE, FILTER_EXCLUDE, FILTER_INCLUDE, FILTER_PID,
+    FILTER_UID_INCLUDE, RING_BUF_SIZE,
+};
+use log::{debug, warn};
+use tokio::io::{unix::AsyncFd, Interest};
+use tokio_stream::Stream;
+
+/// Load time settings for [`ExecSnoop`].
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Size of the ring buffer shared with the kernel, in bytes.
+    ///
+    /// Rounded up to a power of two of at least one page, as required by the kernel.
+    pub ring_buf_size: u32,
+    /// Initial kernel side filters, see [`KernelFilter`] to change them later.
+    pub filters: Filters,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            ring_buf_size: RING_BUF_SIZE,
+            filters: Filters::default(),
+        }
+    }
+}
+
+/// Which execs the kernel reports. Everything passes when empty.
+///
+/// The comm is the one of the process calling exec, which is what [`DecodedExec::comm`] holds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filters {
+    /// Only report execs by these uids, if any.
+    pub uids: Vec<u32>,
+    pub exclude_uids: Vec<u32>,
+    /// Only report execs by these comms, if any.
+    pub comms: Vec<String>,
+    pub exclude_comms: Vec<String>,
+    /// Only report execs by this process and the descendants it forks while traced.
+    pub pid: Option<u32>,
+}
+
+/// The filter maps of a loaded [`ExecSnoop`], see [`ExecSnoop::take_filter`].
+pub struct KernelFilter {
+    flags: Array<MapData, u32>,
+    uids: HashMap<MapData, u32, u8>,
+    comms: HashMap<MapData, [u8; 16], u8>,
+    target_pids: HashMap<MapData, u32, u8>,
+    /// The pid in `target_pids` since the last [`KernelFilter::apply`].
+    target_pid: Option<u32>,
+}
+
+impl KernelFilter {
+    fn new(ebpf: &mut Ebpf) -> anyhow::Result<Self> {
+        Ok(Self {
+            flags: Array::try_from(take_map(ebpf, "FILTER_FLAGS")?)?,
+            uids: HashMap::try_from(take_map(ebpf, "UID_FILTER")?)?,
+            comms: HashMap::try_from(take_map(ebpf, "COMM_FILTER")?)?,
+            target_pids: HashMap::try_from(take_map(ebpf, "TARGET_PIDS")?)?,
+            target_pid: None,
+        })
+    }
+
+    /// Replaces the filters in effect. Takes effect for the execs that start afterwards.
+    ///
+    /// The maps are updated in place, so the filters that stay keep working throughout. The
+    /// descendants tracked for the target pid are only dropped when the target pid changes.
+    pub fn apply(&mut self, filters: &Filters) -> anyhow::Result<()> {
+        let mut flags = 0;
+        let mut uids = std::collections::HashMap::new();
+        for &uid in &filters.uids {
+            uids.insert(uid, FILTER_INCLUDE);
+            flags |= FILTER_UID_INCLUDE;
+        }
+        for &uid in &filters.exclude_uids {
+            uids.insert(uid, FILTER_EXCLUDE);
+        }
+        let mut comms = std::collections::HashMap::new();
+        for comm in &filters.comms {
+            comms.insert(comm_key(comm), FILTER_INCLUDE);
+            flags |= FILTER_COMM_INCLUDE;
+        }
+        for comm in &filters.exclude_comms {
+            comms.insert(comm_key(comm), FILTER_EXCLUDE);
+        }
+        if filters.pid.is_some() {
+            flags |= FILTER_PID;
+        }
+
+        // Filters that go away or are new stay off until their map is up to date.
+        let current = self.flags.get(&0, 0)?;
+        self.flags.set(0, current & flags, 0)?;
+        sync(&mut self.uids, &uids)?;
+        sync(&mut self.comms, &comms)?;
+        if filters.pid != self.target_pid {
+            let keys = self.target_pids.keys().collect::<Result<Vec<_>, _>>()?;
+            for key in keys {
+                self.target_pids.remove(&key)?;
+            }
+            if let Some(pid) = filters.pid {
+                self.target_pids.insert(pid, 1, 0)?;
+            }
+            self.target_pid = filters.pid;
+        }
+        self.flags.set(0, flags, 0)?;
+        Ok(())
+    }
+}
+
+/// Makes `map` hold the entries of `entries`, removing only the keys that are gone.
+fn sync<K: aya::Pod + Eq + std::hash::Hash>(
+    map: &mut HashMap<MapData, K, u8>,
+    entries: &std::collections::HashMap<K, u8>,
+) -> anyhow::Result<()> {
+    let stale = map
+        .keys()
+        .filter(|key| !key.as_ref().is_ok_and(|key| entries.contains_key(key)))
+        .collect::<Result<Vec<_>, _>>()?;
+    for key in stale {
+        map.remove(&key)?;
+    }
+    for (key, value) in entries {
+        if map.get(key, 0).ok() != Some(*value) {
+            map.insert(key, value, 0)?;
+        }
+    }
+    Ok(())
+}
+
+/// The comm as the kernel stores it: at most 15 bytes, NUL padded.
+fn comm_key(comm: &str) -> [u8; 16] {
+    let mut key = [0; 16];
+    let len = comm.len().min(key.len() - 1);
+    key[..len].copy_from_slice(&comm.as_bytes()[..len]);
+    key
+}
+
+/// Traces forks, execs and exits with the `ebpf-kernel` programs, attached while this value lives.
+pub struct ExecSnoop {
+    _ebpf: Ebpf,
+    ring_buf: RingBuf<MapData>,
+    dropped: PerCpuArray<MapData, u64>,
+    filter: Option<KernelFilter>,
+}
+
+impl ExecSnoop {
+    /// Loads the compiled eBPF object, attaches the tracepoints and takes ownership of the
+    /// `RINGBUF` map. Requires root privileges (or `CAP_BPF` + `CAP_PERFMON`).
+    pub fn load(options: &Options) -> anyhow::Result<Self> {
+        bump_memlock_rlimit();
+
+        let ring_buf_size = ring_buf_size(options.ring_buf_size)?;
+        debug!("using a ring buffer of {} bytes", ring_buf_size);
+
+        let fork_child_pid_offset =
+            tracepoint_field_offset("sched", "sched_process_fork", "child_pid")?;
+        // This will include your eBPF object file as raw bytes at compile-time and load it at
+        // runtime. This approach is recommended for most real-world use cases. If you would
+        // like to specify the eBPF program at runtime rather than at compile-time, you can
+        // reach for `Bpf::load_file` instead.
+        let mut ebpf = EbpfLoader::new()
+            .set_max_entries("RINGBUF", ring_buf_size)
+            .set_global("FORK_CHILD_PID_OFFSET", &fork_child_pid_offset, true)
+            .load(aya::include_bytes_aligned!(concat!(
+                env!("OUT_DIR"),
+                "/ebpf-kernel-bpf"
+            )))?;
+        if let Err(e) = aya_log::EbpfLogger::init(&mut ebpf) {
+            // This can happen if you remove all log statements from your eBPF program.
+            warn!("failed to initialize eBPF logger: {}", e);
+        }
+        for (category, name) in [
+            ("syscalls", "sys_enter_execve"),
+            ("syscalls", "sys_exit_execve"),
+            ("syscalls", "sys_enter_execveat"),
+            ("syscalls", "sys_exit_execveat"),
+            ("sched", "sched_process_exit"),
+            ("syscalls", "sys_enter_clone"),
+            ("syscalls", "sys_enter_clone3"),
+            ("sched", "sched_process_fork"),
+        ] {
+            attach_tracepoint(&mut ebpf, category, name)?;
+        }
+        // Not every architecture has these, e.g. arm64 only implements fork and vfork on top
+        // of clone.
+        for name in ["sys_enter_fork", "sys_enter_vfork"] {
+            if let Err(e) = attach_tracepoint(&mut ebpf, "syscalls", name) {
+                debug!("not tracing {name}: {e:#}");
+            }
+        }
+
+        let mut filter = KernelFilter::new(&mut ebpf)?;
+        filter.apply(&options.filters)?;
+
+        let ring_buf = RingBuf::try_from(take_map(&mut ebpf, "RINGBUF")?)?;
+
+        let dropped = PerCpuArray::try_from(take_map(&mut ebpf, "DROPPED")?)?;
+
+        Ok(Self {
+            _ebpf: ebpf,
+            ring_buf,
+            dropped,
+            filter: Some(filter),
+        })
+    }
+
+    /// Hands out the filter maps, e.g. to change the filters from another thread.
+    ///
+    /// Returns `None` when called again.
+    pub fn take_filter(&mut self) -> Option<KernelFilter> {
+        self.filter.take()
+    }
+
+    /// Total number of events the kernel could not submit because the ring buffer was full.
+    pub fn dropped_events(&self) -> anyhow::Result<u64> {
+        Ok(self.dropped.get(&0, 0)?.iter().sum())
+    }
+
+    /// Returns the next event already present in the ring buffer without blocking.
+    ///
+    /// Records that cannot be decoded are skipped with a warning.
+    pub fn next_event(&mut self) -> Option<DecodedEvent> {
+        loop {
+            let item = self.ring_buf.next()?;
+            match DecodedEvent::decode(&item) {
+                Ok(event) => return Some(event),
+                Err(e) => warn!("skipping ring buffer record: {e}"),
+            }
+        }
+    }
+
+    /// Turns the tracer into a [`Stream`] of events, woken up by the ring buffer instead of
+    /// polling it. Has to be called within a tokio runtime.
+    pub fn into_stream(self) -> std::io::Result<EventStream> {
+        Ok(EventStream {
+            snoop: AsyncFd::with_interest(self, Interest::READABLE)?,
+        })
+    }
+}
+
+impl AsRawFd for ExecSnoop {
+    /// The ring buffer, readable when it has events.
+    fn as_raw_fd(&self) -> RawFd {
+        self.ring_buf.as_raw_fd()
+    }
+}
+
+/// The events of an [`ExecSnoop`], see [`ExecSnoop::into_stream`].
+///
+/// Never ends on its own, dropping it detaches the tracepoints.
+pub struct EventStream {
+    snoop: AsyncFd<ExecSnoop>,
+}
+
+impl EventStream {
+    pub fn get_ref(&self) -> &ExecSnoop {
+        self.snoop.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut ExecSnoop {
+        self.snoop.get_mut()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = std::io::Result<DecodedEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let snoop = &mut self.get_mut().snoop;
+        loop {
+            let mut guard = ready!(snoop.poll_read_ready_mut(cx))?;
+            if let Some(event) = guard.get_inner_mut().next_event() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            // Drained, the next wakeup comes once the kernel submits another record.
+            guard.clear_ready();
+        }
+    }
+}
+
+/// The wall clock time of the boot in nanoseconds since the epoch, to turn the `CLOCK_BOOTTIME`
+/// timestamps of the events into wall clock time by adding it.
+///
+/// Calibrated by reading the wall clock between two readings of the boot clock, keeping the
+/// closest pair out of a few. It changes whenever the wall clock is set, so it is worth calling
+/// again every now and then.
+pub fn boot_time_ns() -> std::io::Result<i64> {
+    let mut best: Option<(i64, i64)> = None;
+    for _ in 0..CALIBRATION_ROUNDS {
+        let before = clock_ns(libc::CLOCK_BOOTTIME)?;
+        let realtime = clock_ns(libc::CLOCK_REALTIME)?;
+        let after = clock_ns(libc::CLOCK_BOOTTIME)?;
+        let spread = after - before;
+        if best.is_none_or(|(best_spread, _)| spread < best_spread) {
+            best = Some((spread, realtime - (before + spread / 2)));
+        }
+    }
+    Ok(best.map(|(_, boot_time)| boot_time).unwrap_or_default())
+}
+
+/// How many times [`boot_time_ns`] reads the clocks.
+const CALIBRATION_ROUNDS: usize = 8;
+
+fn clock_ns(clock: libc::clockid_t) -> std::io::Result<i64> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(clock, &mut ts) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(ts.tv_sec * 1_000_000_000 + ts.tv_nsec)
+}
+
+/// Absolute path of the executed file, resolving the filename against the directory it is
+/// relative to through `/proc`.
+///
+/// Best effort: it has to be called while the process is still around, and a close on exec
+/// `dirfd` is gone once the exec succeeded. A successful exec is resolved through
+/// `/proc/<pid>/exe` instead, which does not have this problem.
+pub fn resolve_path(event: &DecodedExec) -> Option<PathBuf> {
+    let proc = PathBuf::from(format!("/proc/{}", event.header.pid));
+    if event.header.ret == 0 {
+        if let Ok(exe) = std::fs::read_link(proc.join("exe")) {
+            return Some(exe);
+        }
+    }
+    resolve_filename(
+        &event.filename,
+        event.header.dirfd,
+        event.header.at_flags,
+        |dir| std::fs::read_link(proc.join(dir)).ok(),
+    )
+}
+
+/// Resolves an `execveat` style `filename` relative to `dirfd`, looking up the paths of
+/// `/proc/<pid>` entries like `cwd` or `fd/3` with `read_link`.
+fn resolve_filename(
+    filename: &str,
+    dirfd: i32,
+    at_flags: u32,
+    read_link: impl Fn(&Path) -> Option<PathBuf>,
+) -> Option<PathBuf> {
+    let path = Path::new(filename);
+    if path.is_absolute() {
+        return Some(path.to_path_buf());
+    }
+    let dir = if dirfd == AT_FDCWD {
+        read_link(Path::new("cwd"))?
+    } else {
+        read_link(&Path::new("fd").join(dirfd.to_string()))?
+    };
+    if filename.is_empty() {
+        // Without `AT_EMPTY_PATH` an empty filename fails with ENOENT and names nothing.
+        return (at_flags & AT_EMPTY_PATH != 0).then_some(dir);
+    }
+    Some(dir.join(path))
+}
+
+fn take_map(ebpf: &mut Ebpf, name: &str) -> anyhow::Result<aya::maps::Map> {
+    ebpf.take_map(name)
+        .ok_or_else(|| anyhow::anyhow!("map {name} not found"))
+}
+
+fn attach_tracepoint(ebpf: &mut Ebpf, category: &str, name: &str) -> anyhow::Result<()> {
+    let program: &mut TracePoint = ebpf
+        .program_mut(name)
+        .ok_or_else(|| anyhow::anyhow!("program {name} not found"))?
+        .try_into()?;
+    program.load()?;
+    program.attach(category, name)?;
+    Ok(())
+}
+
+/// Reads the offset of `field` in the records of a tracepoint from its format in tracefs.
+fn tracepoint_field_offset(category: &str, name: &str, field: &str) -> anyhow::Result<u32> {
+    let path = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"]
+        .iter()
+        .map(|root| format!("{root}/events/{category}/{name}/format"))
+        .find(|path| Path::new(path).exists())
+        .ok_or_else(|| anyhow::anyhow!("tracefs is not mounted"))?;
+    let format = std::fs::read_to_string(&path)?;
+    field_offset(&format, field).ok_or_else(|| anyhow::anyhow!("no field {field} in {path}"))
+}
+
+/// Finds the offset of `field` in a tracepoint format, i.e. in a line like
+/// `field:pid_t child_pid; offset:20; size:4; signed:1;`, with tabs after the semicolons.
+fn field_offset(format: &str, field: &str) -> Option<u32> {
+    format.lines().find_map(|line| {
+        let mut parts = line.trim().split(';').map(str::trim);
+        let declaration = parts.next()?.strip_prefix("field:")?;
+        // Arrays are declared like `char comm[16]`.
+        let name = declaration.rsplit(' ').next()?.split('[').next()?;
+        if name != field {
+            return None;
+        }
+        parts.next()?.strip_prefix("offset:")?.parse().ok()
+    })
+}
+
+/// Rounds `requested` up to a size the kernel accepts for a ring buffer.
+fn ring_buf_size(requested: u32) -> anyhow::Result<u32> {
+    requested
+        .max(page_size())
+        .checked_next_power_of_two()
+        .ok_or_else(|| anyhow::anyhow!("ring buffer size {requested} is too large"))
+}
+
+fn page_size() -> u32 {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u32 }
+}
+
+/// Bump the memlock rlimit. This is needed for older kernels that don't use the
+/// new memcg based accounting, see https://lwn.net/Articles/837122/
+fn bump_memlock_rlimit() {
+    let rlim = libc::rlimit {
+        rlim_cur: libc::RLIM_INFINITY,
+        rlim_max: libc::RLIM_INFINITY,
+    };
+    let ret = unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &rlim) };
+    if ret != 0 {
+        debug!("remove limit on locked memory failed, ret is: {}", ret);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_filename() {
+        let read_link = |dir: &Path| match dir.to_str()? {
+            "cwd" => Some(PathBuf::from("/home/user")),
+            "fd/3" => Some(PathBuf::from("/usr/bin")),
+            _ => None,
+        };
+        let resolve =
+            |filename, dirfd, at_flags| resolve_filename(filename, dirfd, at_flags, read_link);
+        assert_eq!(resolve("/bin/ls", 3, 0).unwrap(), Path::new("/bin/ls"));
+        assert_eq!(
+            resolve("bin/ls", AT_FDCWD, 0).unwrap(),
+            Path::new("/home/user/bin/ls")
+        );
+        assert_eq!(resolve("ls", 3, 0).unwrap(), Path::new("/usr/bin/ls"));
+        assert_eq!(
+            resolve("", 3, AT_EMPTY_PATH).unwrap(),
+            Path::new("/usr/bin")
+        );
+        assert!(resolve("", 3, 0).is_none());
+        assert!(resolve("ls", 4, 0).is_none());
+    }
+
+    #[test]
+    fn test_field_offset() {
+        let format = "name: sched_process_fork
+ID: 315
+format:
+	field:unsigned short common_type;	offset:0;	size:2;	signed:0;
+	field:int common_pid;	offset:4;	size:4;	signed:1;
+
+	field:char parent_comm[16];	offset:8;	size:16;	signed:0;
+	field:pid_t parent_pid;	offset:24;	size:4;	signed:1;
+	field:char child_comm[16];	offset:28;	size:16;	signed:0;
+	field:pid_t child_pid;	offset:44;	size:4;	signed:1;
+";
+        assert_eq!(field_offset(format, "child_pid"), Some(44));
+        assert_eq!(field_offset(format, "child_comm"), Some(28));
+        assert_eq!(field_offset(format, "pid"), None);
+    }
+
+    #[test]
+    fn test_comm_key() {
+        assert_eq!(&comm_key("ls"), b"ls\0\0\0\0\0\0\0\0\0\0\0\0\0\0");
+        assert_eq!(&comm_key("a-very-long-command"), b"a-very-long-com\0");
+    }
+
+    #[test]
+    fn test_ring_buf_size() {
+        let page_size = page_size();
+        assert_eq!(ring_buf_size(0).unwrap(), page_size);
+        assert_eq!(ring_buf_size(page_size + 1).unwrap(), page_size * 2);
+        assert_eq!(ring_buf_size(RING_BUF_SIZE).unwrap(), RING_BUF_SIZE);
+        assert!(ring_buf_size(u32::MAX).is_err());
+    }
+}
@@ -1,18 +1,30 @@
 use std::{
-    borrow::{Borrow, BorrowMut},
-    cell::RefCell,
-    collections::VecDeque,
+    cell::{Ref, RefCell},
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    time::Instant,
 };
 
 use crate::{
-    ProcessExecution,
+    columns::{format_time, Column, ColumnLayout, TimeDisplay},
+    config::{Config, Sort},
+    detail::ProcessDetail,
+    enrich::Enricher,
+    errno,
     event::{AppEvent, Event, EventHandler},
+    filter::ProcessFilter,
+    group::{self, ProcessGroup},
     process_service::ProcessService,
+    process_tree::ProcessTree,
+    source::{Backend, FilterControl, KernelFilters},
+    tree_view::{self, TreeNode},
+    ExecKey, Lifetime, ProcessExecution, ProcessExit, ProcessFork, ProcessKey,
 };
+use chrono::{DateTime, Local};
 use ratatui::{
-    DefaultTerminal,
     crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
     widgets::{ScrollbarState, TableState},
+    DefaultTerminal,
 };
 
 use unicode_width::UnicodeWidthStr;
@@ -26,9 +38,36 @@ pub struct LongestItenLens {
     pub(crate) pid: u16,
     pub(crate) ppid: u16,
     pub(crate) comm: u16,
+    pub(crate) ret: u16,
+    pub(crate) exit: u16,
+    pub(crate) duration: u16,
+    pub(crate) gid: u16,
+    pub(crate) cwd: u16,
+    pub(crate) container: u16,
     pub(crate) args: u16,
 }
 
+impl LongestItenLens {
+    /// The width of `column`, at least that of its header.
+    pub fn get(&self, column: Column) -> u16 {
+        let len = match column {
+            Column::Timestamp => self.timestamp,
+            Column::User => self.username,
+            Column::Gid => self.gid,
+            Column::Pid => self.pid,
+            Column::Ppid => self.ppid,
+            Column::Comm => self.comm,
+            Column::Ret => self.ret,
+            Column::Exit => self.exit,
+            Column::Duration => self.duration,
+            Column::Cwd => self.cwd,
+            Column::Container => self.container,
+            Column::Args => return self.args,
+        };
+        len.max(column.name().len() as u16)
+    }
+}
+
 impl Default for LongestItenLens {
     fn default() -> Self {
         Self {
@@ -37,20 +76,97 @@ impl Default for LongestItenLens {
             pid: 15,
             ppid: 20,
             comm: 15,
+            ret: 3,
+            exit: 4,
+            duration: 8,
+            gid: 5,
+            cwd: 20,
+            container: 12,
             args: 20,
         }
     }
 }
 
+/// The state of the backend, as reported by the reader thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendStatus {
+    /// Nothing reported yet.
+    Starting,
+    Running(Backend),
+    /// The backend ran out of events, like a replay at its end.
+    Exited(Backend),
+    /// The backend failed and is not restarted.
+    Failed(Backend),
+    /// The backend failed `attempt` times in a row and is opened again `at` that point.
+    Restarting {
+        backend: Backend,
+        attempt: u32,
+        at: Instant,
+    },
+}
+
 /// Application.
 #[derive(Debug)]
 pub struct App {
     /// Is the application running?
     pub running: bool,
 
-    /// Ring of processes. Bounded to 50
+    /// Ring of processes, bounded to `history_size`.
     pub processes: VecDeque<ProcessExecution>,
 
+    /// How many processes are kept.
+    pub history_size: usize,
+
+    /// Numbers of the rows of each pid, oldest first, so that they are found without a scan.
+    ///
+    /// Rows are numbered as they are added, the one numbered `n` is at `n - evicted` in
+    /// `processes`.
+    rows_by_pid: HashMap<u32, VecDeque<usize>>,
+
+    /// How many rows were evicted from the front of `processes`.
+    evicted: usize,
+
+    /// How many rows each parent has, to drop the synthetic parents no row refers to anymore.
+    children_by_parent: HashMap<ProcessKey, usize>,
+
+    /// Whether the selection sticks to the newest row, toggled with `f`.
+    ///
+    /// Otherwise it stays on the selected row while new events arrive.
+    pub follow_tail: bool,
+
+    /// The row the selection stays on when not following the tail.
+    selected_key: Option<RowKey>,
+
+    /// The order and visibility of the columns, changed in the chooser opened with `c`.
+    pub columns: ColumnLayout,
+
+    /// The cursor in the column chooser, `None` unless it is open.
+    pub column_chooser: Option<usize>,
+
+    /// The column the rows are sorted by, set with `s`. Not applied in the tree view.
+    pub sort: Option<Sort>,
+
+    /// How the timestamp column shows the time, cycled with `T`.
+    pub time_display: TimeDisplay,
+
+    /// The time of the first exec seen, what relative times are relative to.
+    pub first_timestamp: Option<DateTime<Local>>,
+
+    /// Where the columns and sort order are saved, not at all if `None`.
+    config_path: Option<PathBuf>,
+
+    /// Whether the rows are frozen, toggled with `p`.
+    pub paused: bool,
+
+    /// Events changing the rows that arrived while paused, applied when resuming.
+    backlog: VecDeque<AppEvent>,
+
+    /// How many of the `backlog` events are new processes.
+    pub backlog_new_processes: usize,
+
+    /// Events discarded because the `backlog` was full.
+    pub discarded_events: u64,
+
     /// Event handler.
     pub events: EventHandler,
 
@@ -60,26 +176,188 @@ pub struct App {
     /// Scrollbar state.
     pub scroll_state: RefCell<ScrollbarState>,
 
-    pub longest_item_lens: LongestItenLens,
-
     pub debug_message: String,
 
+    /// Total number of events the backend reported as lost.
+    pub dropped_events: u64,
+
+    /// Total number of lines of backend output that could not be parsed.
+    pub malformed_lines: u64,
+
+    /// What the backend is up to, shown in the footer.
+    pub backend_status: BackendStatus,
+
     pub process_service: ProcessService,
+
+    /// Looks up the parents of rows whose exec was not seen, `None` for replays.
+    enricher: Option<Enricher>,
+
+    /// Processes seen forking or executing that did not exit yet.
+    pub process_tree: ProcessTree,
+
+    /// Filters the backend applies, changed with the `x` and `X` keys.
+    pub kernel_filters: KernelFilters,
+
+    /// The filters from the command line, restored with `X`.
+    initial_kernel_filters: KernelFilters,
+
+    /// `None` if the backend does not support kernel filters.
+    filter_control: Option<Box<dyn FilterControl>>,
+
+    /// Only rows matching it are shown, the others are kept for when it is cleared.
+    pub filter: Option<ProcessFilter>,
+
+    /// What is typed into the filter bar, `None` unless it is open.
+    pub filter_input: Option<String>,
+
+    /// Why `filter_input` is not a valid filter.
+    pub filter_error: Option<String>,
+
+    /// Whether similar executions are collapsed into groups, toggled with `g`.
+    pub grouping: bool,
+
+    /// Fingerprints of the groups showing their members, toggled with space.
+    pub expanded_groups: HashSet<String>,
+
+    /// The pane opened with `Enter`, covering the table.
+    pub detail: Option<ProcessDetail>,
+
+    /// Lines of the detail pane scrolled past.
+    pub detail_scroll: u16,
+
+    /// Whether rows are nested under their parents, toggled with `t`.
+    pub tree_view: bool,
+
+    /// Nodes of the tree view hiding their children, toggled with space.
+    pub collapsed_nodes: HashSet<ExecKey>,
+
+    /// Running parents of rows whose exec was not seen, by the key the rows refer to them by.
+    pub synthetic_parents: HashMap<ProcessKey, ProcessExecution>,
+
+    /// The rows of the table, `None` until they are built again after a change.
+    rows: RefCell<Option<Rows>>,
+}
+
+/// The rows of the table, kept between frames as positions in [`App::processes`].
+#[derive(Debug)]
+struct Rows {
+    rows: Vec<CachedRow>,
+    /// How many processes match the filter.
+    visible: usize,
 }
 
-const MAX_ITEMS_COUNT: usize = 50;
+/// A [`TableRow`] by the positions of its processes.
+#[derive(Debug)]
+enum CachedRow {
+    Process(usize),
+    Group {
+        fingerprint: String,
+        members: Vec<usize>,
+        expanded: bool,
+    },
+    Member(usize),
+    Node {
+        /// `Err` with the key of synthetic parents.
+        position: Result<usize, ProcessKey>,
+        depth: usize,
+        has_children: bool,
+        collapsed: bool,
+    },
+}
+
+/// A row of the table.
+#[derive(Debug)]
+pub enum TableRow<'a> {
+    Process(&'a ProcessExecution),
+    /// Executions collapsed into one row, followed by [`TableRow::Member`]s if expanded.
+    Group {
+        group: ProcessGroup<'a>,
+        expanded: bool,
+    },
+    Member(&'a ProcessExecution),
+    Node(TreeNode<'a>),
+}
+
+/// Identifies a [`TableRow`] across redraws.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowKey {
+    Process(ExecKey),
+    Group(String),
+}
+
+impl<'a> TableRow<'a> {
+    pub fn key(&self) -> RowKey {
+        match self {
+            TableRow::Group { group, .. } => RowKey::Group(group.fingerprint.clone()),
+            _ => RowKey::Process(self.process().exec_key()),
+        }
+    }
+
+    /// The process the row shows, the most recent member for groups.
+    pub fn process(&self) -> &'a ProcessExecution {
+        match self {
+            TableRow::Process(process) | TableRow::Member(process) => process,
+            TableRow::Group { group, .. } => group.last(),
+            TableRow::Node(node) => node.process,
+        }
+    }
+}
+
+/// How many processes are kept by default.
+pub const DEFAULT_HISTORY_SIZE: usize = 10_000;
+
+/// Events kept while paused per row of the history: the exec, and the fork, exit and
+/// enrichment of its process.
+const BACKLOG_EVENTS_PER_ROW: usize = 4;
+
+/// Rows moved by `PageUp` and `PageDown`.
+const PAGE_SIZE: usize = 20;
 
 impl Default for App {
     fn default() -> Self {
         Self {
             running: true,
-            longest_item_lens: LongestItenLens::default(),
-            processes: VecDeque::with_capacity(MAX_ITEMS_COUNT),
+            processes: VecDeque::new(),
+            rows_by_pid: HashMap::new(),
+            evicted: 0,
+            children_by_parent: HashMap::new(),
+            history_size: DEFAULT_HISTORY_SIZE,
+            follow_tail: true,
+            selected_key: None,
+            columns: ColumnLayout::default(),
+            column_chooser: None,
+            sort: None,
+            time_display: TimeDisplay::default(),
+            first_timestamp: None,
+            config_path: None,
+            paused: false,
+            backlog: VecDeque::new(),
+            backlog_new_processes: 0,
+            discarded_events: 0,
             events: EventHandler::new(),
             state: RefCell::new(TableState::default().with_selected(0)),
-            scroll_state: RefCell::new(ScrollbarState::new(MAX_ITEMS_COUNT * ITEM_HEIGHT)),
+            scroll_state: RefCell::new(ScrollbarState::new(0)),
             debug_message: String::new(),
+            dropped_events: 0,
+            malformed_lines: 0,
+            backend_status: BackendStatus::Starting,
             process_service: ProcessService::new(),
+            enricher: None,
+            process_tree: ProcessTree::new(),
+            kernel_filters: KernelFilters::default(),
+            initial_kernel_filters: KernelFilters::default(),
+            filter_control: None,
+            filter: None,
+            filter_input: None,
+            filter_error: None,
+            grouping: false,
+            expanded_groups: HashSet::new(),
+            detail: None,
+            detail_scroll: 0,
+            tree_view: false,
+            collapsed_nodes: HashSet::new(),
+            synthetic_parents: HashMap::new(),
+            rows: RefCell::new(None),
         }
     }
 }
@@ -90,33 +368,147 @@ impl App {
         Self::default()
     }
 
+    pub fn with_enricher(mut self, enricher: Option<Enricher>) -> Self {
+        self.enricher = enricher;
+        self
+    }
+
+    /// Keeps at most `history_size` processes.
+    pub fn with_history_size(mut self, history_size: usize) -> Self {
+        self.history_size = history_size.max(1);
+        self
+    }
+
     /// Run the application's main loop.
     pub fn run(mut self, mut terminal: DefaultTerminal) -> color_eyre::Result<()> {
         while self.running {
+            self.sync_selection();
             terminal.draw(|frame| frame.render_widget(&self, frame.area()))?;
-            self.handle_events()?;
+            let event = self.events.next()?;
+            self.handle_event(event)?;
+            // Catch up with bursts of events before drawing again.
+            while let Some(event) = self.events.try_next() {
+                self.handle_event(event)?;
+            }
         }
         Ok(())
     }
 
-    pub fn handle_events(&mut self) -> color_eyre::Result<()> {
-        match self.events.next()? {
+    pub fn handle_event(&mut self, event: Event) -> color_eyre::Result<()> {
+        match event {
             Event::Tick => self.tick(),
-            Event::Crossterm(event) => match event {
-                crossterm::event::Event::Key(key_event) => self.handle_key_event(key_event)?,
-                _ => {}
-            },
-            Event::App(app_event) => match app_event {
-                AppEvent::NewProcess(process) => self.add_process(process),
-                AppEvent::Print(msg) => self.print_msg(msg),
-                AppEvent::Quit => self.quit(),
-            },
+            Event::Crossterm(event) => {
+                if let crossterm::event::Event::Key(key_event) = event {
+                    self.handle_key_event(key_event)?
+                }
+            }
+            Event::App(app_event) => self.handle_app_event(*app_event),
         }
         Ok(())
     }
 
+    fn handle_app_event(&mut self, app_event: AppEvent) {
+        if self.paused && app_event.changes_rows() {
+            // Older rows would be evicted on resuming anyway.
+            if self.backlog.len() >= self.history_size * BACKLOG_EVENTS_PER_ROW {
+                if let Some(AppEvent::NewProcess(_)) = self.backlog.pop_front() {
+                    self.backlog_new_processes -= 1;
+                }
+                self.discarded_events += 1;
+            }
+            if matches!(app_event, AppEvent::NewProcess(_)) {
+                self.backlog_new_processes += 1;
+            }
+            self.backlog.push_back(app_event);
+            return;
+        }
+        match app_event {
+            AppEvent::NewProcess(process) => self.add_process(process),
+            AppEvent::ProcessExited(exit) => self.mark_exited(exit),
+            AppEvent::ProcessForked(fork) => self.add_fork(fork),
+            AppEvent::ProcessEnriched {
+                key,
+                timestamp,
+                snapshot,
+            } => {
+                let row = self.rows_of(key.pid).rev().find(|&i| {
+                    let process = &self.processes[i];
+                    process.key().matches(key) && process.timestamp == timestamp
+                });
+                if let Some(i) = row {
+                    self.processes[i].snapshot = Some(snapshot);
+                    self.invalidate_rows();
+                }
+            }
+            AppEvent::ParentFound { key, parent } => {
+                // Unless the rows referring to it are gone, or its exec was seen after all.
+                if self.children_by_parent.contains_key(&key)
+                    && !self
+                        .rows_of(key.pid)
+                        .any(|i| self.processes[i].key().matches(key))
+                {
+                    self.synthetic_parents.insert(key, parent);
+                    self.invalidate_rows();
+                }
+            }
+            AppEvent::EventsDropped { total, .. } => self.dropped_events = total,
+            AppEvent::MalformedOutput { total, error } => {
+                self.malformed_lines = total;
+                self.print_msg(format!("could not parse the backend output: {error}"));
+            }
+            AppEvent::BackendStarted(backend) => {
+                self.backend_status = BackendStatus::Running(backend)
+            }
+            AppEvent::BackendExited(backend) => {
+                self.backend_status = BackendStatus::Exited(backend)
+            }
+            AppEvent::BackendFailed { backend, error } => {
+                self.backend_status = BackendStatus::Failed(backend);
+                // The footer has room for a line or two, the stderr tail goes on the same one.
+                let error = error.lines().map(str::trim).collect::<Vec<_>>().join(" | ");
+                self.print_msg(format!("{backend} failed: {error}"));
+            }
+            AppEvent::BackendRestarting {
+                backend,
+                attempt,
+                delay,
+            } => {
+                self.backend_status = BackendStatus::Restarting {
+                    backend,
+                    attempt,
+                    at: Instant::now() + delay,
+                }
+            }
+            AppEvent::Print(msg) => self.print_msg(msg),
+            AppEvent::Quit => self.quit(),
+        }
+    }
+
+    /// Freezes the rows, or merges in what arrived in the meantime.
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if !self.paused {
+            self.backlog_new_processes = 0;
+            while let Some(app_event) = self.backlog.pop_front() {
+                self.handle_app_event(app_event);
+            }
+        }
+    }
+
     /// Handles the key events and updates the state of [`App`].
     pub fn handle_key_event(&mut self, key_event: KeyEvent) -> color_eyre::Result<()> {
+        if self.filter_input.is_some() {
+            self.handle_filter_key_event(key_event);
+            return Ok(());
+        }
+        if self.detail.is_some() {
+            self.handle_detail_key_event(key_event);
+            return Ok(());
+        }
+        if self.column_chooser.is_some() {
+            self.handle_column_chooser_key_event(key_event);
+            return Ok(());
+        }
         match key_event.code {
             KeyCode::Esc | KeyCode::Char('q') => self.events.send(AppEvent::Quit),
             KeyCode::Char('c' | 'C') if key_event.modifiers == KeyModifiers::CONTROL => {
@@ -124,28 +516,461 @@ impl App {
             }
             KeyCode::Char('j') | KeyCode::Down => self.next_row(),
             KeyCode::Char('k') | KeyCode::Up => self.previous_row(),
+            KeyCode::PageDown => self.move_selection(PAGE_SIZE as isize),
+            KeyCode::PageUp => self.move_selection(-(PAGE_SIZE as isize)),
+            KeyCode::Home => self.select_first_row(),
+            KeyCode::End | KeyCode::Char('G') => self.follow_tail = true,
+            KeyCode::Char('f') => self.follow_tail = !self.follow_tail,
+            KeyCode::Char('p') => self.toggle_pause(),
+            KeyCode::Char('s') => self.cycle_sort(),
+            KeyCode::Char('c') => self.column_chooser = Some(0),
             KeyCode::Char('l') | KeyCode::Right => self.next_column(),
             KeyCode::Char('h') | KeyCode::Left => self.previous_column(),
 
+            KeyCode::Enter => self.open_detail(),
+            KeyCode::Char('x') => {
+                if let Some(process) = self.selected_process() {
+                    let mut filters = self.kernel_filters.clone();
+                    filters.exclude_comms.push(process.comm.clone());
+                    self.set_kernel_filters(filters);
+                }
+            }
+            KeyCode::Char('X') => self.set_kernel_filters(self.initial_kernel_filters.clone()),
+            KeyCode::Char('/') => {
+                let input = self
+                    .filter
+                    .as_ref()
+                    .map(|f| f.to_string())
+                    .unwrap_or_default();
+                self.filter_input = Some(input);
+            }
+            KeyCode::Char('g') => {
+                self.grouping = !self.grouping;
+                self.tree_view = false;
+                self.invalidate_rows();
+            }
+            KeyCode::Char('t') => {
+                self.tree_view = !self.tree_view;
+                self.grouping = false;
+                self.invalidate_rows();
+            }
+            KeyCode::Char('T') => {
+                self.time_display = self.time_display.next();
+                self.save_config();
+            }
+            KeyCode::Char(' ') => self.toggle_selected_row(),
+            // Other handlers you could add here.
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Handles the key events while the detail pane is open.
+    fn handle_detail_key_event(&mut self, key_event: KeyEvent) {
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.detail = None,
+            KeyCode::Char('c' | 'C') if key_event.modifiers == KeyModifiers::CONTROL => {
+                self.events.send(AppEvent::Quit)
+            }
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.detail_scroll = self.detail_scroll.saturating_add(1)
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.detail_scroll = self.detail_scroll.saturating_sub(1)
+            }
+            KeyCode::PageDown => self.detail_scroll = self.detail_scroll.saturating_add(10),
+            KeyCode::PageUp => self.detail_scroll = self.detail_scroll.saturating_sub(10),
+            _ => {}
+        }
+    }
+
+    /// Opens the detail pane for the selected row.
+    fn open_detail(&mut self) {
+        let Some(process) = self.selected_process().cloned() else {
+            return;
+        };
+        // Once replaced or exited, /proc would describe something else or nothing.
+        let running = match process.lifetime {
+            Lifetime::Running | Lifetime::Unknown => {
+                self.process_service.get_process(process.key())
+            }
+            Lifetime::Replaced(_) | Lifetime::Exited(_) => None,
+        };
+        let known = self
+            .process_tree
+            .ancestors(process.key())
+            .collect::<Vec<_>>();
+        // Continue with what is still running above the last process seen forking.
+        let next = match known.last() {
+            Some(node) => node.parent,
+            None => match running.as_ref().and_then(|r| r.ppid) {
+                // Reparented, the original parent is gone.
+                Some(ppid) if ppid != process.ppid => ProcessKey::new(ppid, None),
+                _ => process.parent_key(),
+            },
+        };
+        let mut ancestors = known
+            .into_iter()
+            .map(|node| (node.key.pid, node.comm.clone()))
+            .collect::<Vec<_>>();
+        ancestors.extend(self.process_service.ancestors(next));
+
+        self.detail = Some(ProcessDetail::new(&process, running, &ancestors));
+        self.detail_scroll = 0;
+    }
+
+    /// Handles the key events while the filter bar is open.
+    ///
+    /// The filter is updated on every change, `Enter` closes the bar and `Esc` clears the filter.
+    fn handle_filter_key_event(&mut self, key_event: KeyEvent) {
+        let Some(input) = self.filter_input.as_mut() else {
+            return;
+        };
+        match key_event.code {
             KeyCode::Enter => {
-                let selected = self.state.borrow().selected();
-                if let Some(i) = selected {
-                    let process = self.processes.get(i).unwrap();
-                    let pid = process.pid;
-                    let ppid = process.ppid;
+                self.filter_input = None;
+                return;
+            }
+            KeyCode::Esc => {
+                self.filter_input = None;
+                self.set_filter("");
+                return;
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char('c' | 'C') if key_event.modifiers == KeyModifiers::CONTROL => {
+                self.events.send(AppEvent::Quit);
+                return;
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => return,
+        }
+        let input = input.clone();
+        self.set_filter(&input);
+    }
 
-                    let process = self.process_service.get_process(pid as usize);
-                    let parent_process = self.process_service.get_process(ppid as usize);
+    /// Shows only the rows matching `input`, or all of them if it is empty.
+    ///
+    /// An invalid regex leaves the previous filter in place.
+    pub fn set_filter(&mut self, input: &str) {
+        if input.is_empty() {
+            self.filter = None;
+            self.filter_error = None;
+            self.invalidate_rows();
+        } else {
+            match ProcessFilter::parse(input) {
+                Ok(filter) => {
+                    self.filter = Some(filter);
+                    self.filter_error = None;
+                    self.invalidate_rows();
+                }
+                Err(e) => {
+                    self.filter_error = Some(e.to_string());
+                }
+            }
+        }
+    }
 
-                    let msg = format!("pid: {:?}\n ppid: {:?}", process, parent_process);
+    fn select_first_row(&mut self) {
+        self.select(0);
+    }
 
-                    self.print_msg(msg);
+    /// Selects the row at `index` and stops following the tail.
+    fn select(&mut self, index: usize) {
+        self.follow_tail = false;
+        self.selected_key = None;
+        self.state.borrow_mut().select(Some(index));
+    }
+
+    /// Moves the selection by `delta` rows, stopping at the first and last one.
+    fn move_selection(&mut self, delta: isize) {
+        let last = self.row_count().saturating_sub(1);
+        let selected = self.state.borrow().selected().unwrap_or(0);
+        self.select(selected.saturating_add_signed(delta).min(last));
+    }
+
+    /// Puts the selection back on the row it was on after rows were added, removed or moved.
+    ///
+    /// Follows the newest row instead if [`App::follow_tail`] is on.
+    pub fn sync_selection(&mut self) {
+        let len = self.row_count();
+        let current = self.state.borrow().selected();
+        let selected = if len == 0 {
+            None
+        } else if self.follow_tail {
+            Some(len - 1)
+        } else {
+            self.selected_key
+                .as_ref()
+                // The selected row usually did not move.
+                .and_then(|key| {
+                    current
+                        .filter(|&i| self.row_key(i).as_ref() == Some(key))
+                        .or_else(|| (0..len).find(|&i| self.row_key(i).as_ref() == Some(key)))
+                })
+                .or(current)
+                .map(|i| i.min(len - 1))
+        };
+        self.selected_key = selected.and_then(|i| self.row_key(i));
+        self.state.borrow_mut().select(selected);
+        self.scroll_state = RefCell::new(
+            self.scroll_state
+                .take()
+                .content_length(len * ITEM_HEIGHT)
+                .position(selected.unwrap_or(0) * ITEM_HEIGHT),
+        );
+    }
+
+    /// Expands the selected group or tree node, or collapses it again.
+    fn toggle_selected_row(&mut self) {
+        let Some(selected) = self.state.borrow().selected() else {
+            return;
+        };
+        match self.table_row(selected) {
+            Some(TableRow::Group { group, .. }) => {
+                let fingerprint = group.fingerprint;
+                if !self.expanded_groups.remove(&fingerprint) {
+                    self.expanded_groups.insert(fingerprint);
                 }
             }
-            // Other handlers you could add here.
+            Some(TableRow::Node(node)) if node.has_children => {
+                let key = node.process.exec_key();
+                if !self.collapsed_nodes.remove(&key) {
+                    self.collapsed_nodes.insert(key);
+                }
+            }
+            _ => return,
+        }
+        self.invalidate_rows();
+    }
+
+    /// How many processes match the filter.
+    pub fn visible_count(&self) -> usize {
+        self.rows().visible
+    }
+
+    /// How many rows the table has.
+    pub fn row_count(&self) -> usize {
+        self.rows().rows.len()
+    }
+
+    /// The row at `index`, grouped or nested if [`App::grouping`] or [`App::tree_view`] is on.
+    pub fn table_row(&self, index: usize) -> Option<TableRow<'_>> {
+        let rows = self.rows();
+        let row = match rows.rows.get(index)? {
+            &CachedRow::Process(i) => TableRow::Process(&self.processes[i]),
+            CachedRow::Group {
+                fingerprint,
+                members,
+                expanded,
+            } => TableRow::Group {
+                group: ProcessGroup {
+                    fingerprint: fingerprint.clone(),
+                    members: members.iter().map(|&i| &self.processes[i]).collect(),
+                    positions: members.clone(),
+                },
+                expanded: *expanded,
+            },
+            &CachedRow::Member(i) => TableRow::Member(&self.processes[i]),
+            &CachedRow::Node {
+                position,
+                depth,
+                has_children,
+                collapsed,
+            } => TableRow::Node(TreeNode {
+                process: match position {
+                    Ok(i) => &self.processes[i],
+                    Err(key) => &self.synthetic_parents[&key],
+                },
+                position,
+                depth,
+                has_children,
+                collapsed,
+            }),
+        };
+        Some(row)
+    }
+
+    /// The key of the row at `index`, without collecting the members of groups.
+    fn row_key(&self, index: usize) -> Option<RowKey> {
+        let rows = self.rows();
+        let process = match rows.rows.get(index)? {
+            CachedRow::Group { fingerprint, .. } => {
+                return Some(RowKey::Group(fingerprint.clone()))
+            }
+            &CachedRow::Process(i) | &CachedRow::Member(i) => &self.processes[i],
+            &CachedRow::Node { position, .. } => match position {
+                Ok(i) => &self.processes[i],
+                Err(key) => &self.synthetic_parents[&key],
+            },
+        };
+        Some(RowKey::Process(process.exec_key()))
+    }
+
+    /// The rows of the table, built again if something they depend on changed.
+    fn rows(&self) -> Ref<'_, Rows> {
+        if self.rows.borrow().is_none() {
+            let rows = self.build_rows();
+            *self.rows.borrow_mut() = Some(rows);
+        }
+        Ref::map(self.rows.borrow(), |rows| {
+            rows.as_ref().expect("the rows were just built")
+        })
+    }
+
+    /// Has the rows built again on their next use.
+    fn invalidate_rows(&mut self) {
+        *self.rows.get_mut() = None;
+    }
+
+    fn build_rows(&self) -> Rows {
+        let visible = (0..self.processes.len())
+            .filter(|&i| {
+                let process = &self.processes[i];
+                self.filter.as_ref().is_none_or(|f| f.matches(process))
+            })
+            .collect::<Vec<_>>();
+        let processes = visible.iter().map(|&i| &self.processes[i]);
+        let rows = if self.tree_view {
+            tree_view::flatten(processes, &self.synthetic_parents, &self.collapsed_nodes)
+                .into_iter()
+                .map(|node| CachedRow::Node {
+                    position: node.position.map(|p| visible[p]),
+                    depth: node.depth,
+                    has_children: node.has_children,
+                    collapsed: node.collapsed,
+                })
+                .collect()
+        } else if !self.grouping {
+            let mut rows = processes.enumerate().collect::<Vec<_>>();
+            self.sort_by(&mut rows, |(_, p)| p);
+            rows.into_iter()
+                .map(|(p, _)| CachedRow::Process(visible[p]))
+                .collect()
+        } else {
+            let mut groups = group::group(processes);
+            self.sort_by(&mut groups, |g| g.last());
+            let mut rows = Vec::new();
+            for group in groups {
+                let members = group
+                    .positions
+                    .iter()
+                    .map(|&p| visible[p])
+                    .collect::<Vec<_>>();
+                if let [i] = members[..] {
+                    rows.push(CachedRow::Process(i));
+                    continue;
+                }
+                let expanded = self.expanded_groups.contains(&group.fingerprint);
+                let shown = expanded.then(|| members.clone());
+                rows.push(CachedRow::Group {
+                    fingerprint: group.fingerprint,
+                    members,
+                    expanded,
+                });
+                rows.extend(shown.into_iter().flatten().map(CachedRow::Member));
+            }
+            rows
+        };
+        Rows {
+            rows,
+            visible: visible.len(),
+        }
+    }
+
+    /// Sorts `items` by the column chosen with `s`, if any.
+    fn sort_by<T>(&self, items: &mut [T], process: impl Fn(&T) -> &ProcessExecution) {
+        let Some(sort) = self.sort else {
+            return;
+        };
+        items.sort_by(|a, b| {
+            let ordering = sort.column.compare(process(a), process(b));
+            if sort.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+
+    /// Sorts by the selected column, ascending, then descending, then not at all.
+    fn cycle_sort(&mut self) {
+        let selected = self.state.borrow().selected_column();
+        // The first column is the row number.
+        let Some(column) = selected
+            .and_then(|i| i.checked_sub(1))
+            .and_then(|i| self.columns.visible().nth(i))
+        else {
+            self.print_msg("select a column to sort by with h and l".to_string());
+            return;
+        };
+        self.sort = match self.sort {
+            Some(sort) if sort.column == column && !sort.descending => Some(Sort {
+                column,
+                descending: true,
+            }),
+            Some(sort) if sort.column == column => None,
+            _ => Some(Sort {
+                column,
+                descending: false,
+            }),
+        };
+        self.invalidate_rows();
+        self.save_config();
+    }
+
+    /// Handles the key events while the column chooser is open.
+    fn handle_column_chooser_key_event(&mut self, key_event: KeyEvent) {
+        let Some(cursor) = self.column_chooser else {
+            return;
+        };
+        let last = self.columns.all().len() - 1;
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('c' | 'q') => {
+                self.column_chooser = None;
+                self.save_config();
+            }
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.column_chooser = Some((cursor + 1).min(last))
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.column_chooser = Some(cursor.saturating_sub(1))
+            }
+            KeyCode::Char(' ') => self.columns.toggle(cursor),
+            KeyCode::Char('J') => self.column_chooser = Some(self.columns.move_column(cursor, 1)),
+            KeyCode::Char('K') => self.column_chooser = Some(self.columns.move_column(cursor, -1)),
             _ => {}
         }
-        Ok(())
+    }
+
+    /// Uses the column layout and sort order of `config`, saving changes to `path`.
+    pub fn with_config(mut self, config: &Config, path: Option<PathBuf>) -> Self {
+        self.columns = config.column_layout();
+        self.sort = config.sort;
+        self.invalidate_rows();
+        self.time_display = config.time;
+        self.config_path = path;
+        self
+    }
+
+    fn save_config(&mut self) {
+        let Some(path) = &self.config_path else {
+            return;
+        };
+        let config = Config {
+            columns: self.columns.visible().collect(),
+            sort: self.sort,
+            time: self.time_display,
+        };
+        if let Err(e) = config.save(path) {
+            self.print_msg(format!("failed to save the config: {e:#}"));
+        }
+    }
+
+    fn selected_process(&self) -> Option<&ProcessExecution> {
+        let selected = self.state.borrow().selected()?;
+        self.table_row(selected).map(|row| row.process())
     }
 
     /// Handles the tick event of the terminal.
@@ -159,23 +984,147 @@ impl App {
         self.running = false;
     }
 
+    /// Sets up changing the kernel filters, which start out as `filters`.
+    pub fn with_kernel_filters(
+        mut self,
+        filter_control: Option<Box<dyn FilterControl>>,
+        filters: KernelFilters,
+    ) -> Self {
+        self.filter_control = filter_control;
+        self.initial_kernel_filters = filters.clone();
+        self.kernel_filters = filters;
+        self
+    }
+
+    pub fn set_kernel_filters(&mut self, filters: KernelFilters) {
+        let Some(filter_control) = self.filter_control.as_mut() else {
+            self.print_msg("kernel filters need the aya backend".to_string());
+            return;
+        };
+        match filter_control.apply(&filters) {
+            Ok(()) => self.kernel_filters = filters,
+            Err(e) => self.print_msg(format!("failed to update the kernel filters: {e:#}")),
+        }
+    }
+
     pub fn print_msg(&mut self, msg: String) {
         self.debug_message = msg;
     }
 
-    pub fn add_process(&mut self, process: ProcessExecution) {
-        // guard by max 50 processes
-        if self.processes.len() == MAX_ITEMS_COUNT {
-            self.processes.pop_front();
+    pub fn add_process(&mut self, mut process: ProcessExecution) {
+        self.first_timestamp.get_or_insert(process.timestamp);
+        if self.processes.len() >= self.history_size {
+            self.evict_oldest();
+        }
+        if process.ppid == 0 {
+            // The backend could not tell, but the parent may have been seen forking it.
+            if let Some(node) = self.process_tree.get(process.key()) {
+                process.ppid = node.parent.pid;
+                process.parent_start_time = node.parent.start_time;
+            }
+        }
+        let parent = process.parent_key();
+        let children = self.children_by_parent.entry(parent).or_default();
+        *children += 1;
+        // Looked up once for all the rows referring to it, whether it is found or not.
+        if *children == 1
+            && parent.pid != 0
+            && !self
+                .rows_of(parent.pid)
+                .any(|i| self.processes[i].key().matches(parent))
+        {
+            if let Some(enricher) = &self.enricher {
+                enricher.look_up_parent(parent);
+            }
         }
+        if !process.failed() {
+            self.process_tree
+                .exec(process.key(), parent, process.comm.clone());
+        }
+        if process.lifetime == Lifetime::Running {
+            // The process stopped running whatever it executed before.
+            for i in self.running(process.key()) {
+                let previous = &mut self.processes[i];
+                let duration = (process.timestamp - previous.timestamp)
+                    .to_std()
+                    .unwrap_or_default();
+                previous.lifetime = Lifetime::Replaced(duration);
+            }
+        }
+        let number = self.evicted + self.processes.len();
+        self.rows_by_pid
+            .entry(process.pid)
+            .or_default()
+            .push_back(number);
         self.processes.push_back(process);
-        self.longest_item_lens = constraint_len_calculator(&self.processes);
+        self.invalidate_rows();
+    }
+
+    pub fn add_fork(&mut self, fork: ProcessFork) {
+        self.process_tree
+            .fork(fork.key(), fork.parent_key(), fork.comm);
+    }
+
+    pub fn mark_exited(&mut self, exit: ProcessExit) {
+        self.process_tree.exit(exit.key());
+        for i in self.running(exit.key()) {
+            self.processes[i].lifetime = Lifetime::Exited(exit);
+        }
+        for parent in self.synthetic_parents.values_mut() {
+            if parent.key().matches(exit.key()) {
+                parent.lifetime = Lifetime::Exited(exit);
+            }
+        }
+        self.invalidate_rows();
+    }
+
+    /// Where the rows of `key` that are still running are in `processes`.
+    fn running(&self, key: ProcessKey) -> Vec<usize> {
+        self.rows_of(key.pid)
+            .filter(|&i| {
+                let process = &self.processes[i];
+                process.key().matches(key) && process.lifetime == Lifetime::Running
+            })
+            .collect()
+    }
+
+    /// Where the rows of `pid` are in `processes`, oldest first.
+    fn rows_of(&self, pid: u32) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.rows_by_pid
+            .get(&pid)
+            .into_iter()
+            .flatten()
+            .map(|number| number - self.evicted)
+    }
+
+    /// Drops the oldest row, and the synthetic parent no other row has anymore.
+    fn evict_oldest(&mut self) {
+        let Some(evicted) = self.processes.pop_front() else {
+            return;
+        };
+        self.evicted += 1;
+        if let Some(rows) = self.rows_by_pid.get_mut(&evicted.pid) {
+            // The oldest row overall is the oldest one of its pid.
+            rows.pop_front();
+            if rows.is_empty() {
+                self.rows_by_pid.remove(&evicted.pid);
+            }
+        }
+        let parent = evicted.parent_key();
+        if let Some(children) = self.children_by_parent.get_mut(&parent) {
+            *children -= 1;
+            if *children == 0 {
+                self.children_by_parent.remove(&parent);
+                self.synthetic_parents.remove(&parent);
+            }
+        }
     }
 
     pub fn next_row(&mut self) {
+        let len = self.row_count();
         let i = match self.state.borrow().selected() {
             Some(i) => {
-                if i >= self.processes.len() - 1 {
+                if i + 1 >= len {
                     0
                 } else {
                     i + 1
@@ -183,23 +1132,22 @@ impl App {
             }
             None => 0,
         };
-        self.state.borrow_mut().select(Some(i));
-        self.scroll_state = RefCell::new(self.scroll_state.take().position(i * ITEM_HEIGHT));
+        self.select(i);
     }
 
     pub fn previous_row(&mut self) {
+        let len = self.row_count();
         let i = match self.state.borrow().selected() {
             Some(i) => {
                 if i == 0 {
-                    self.processes.len() - 1
+                    len.saturating_sub(1)
                 } else {
                     i - 1
                 }
             }
             None => 0,
         };
-        self.state.borrow_mut().select(Some(i));
-        self.scroll_state = RefCell::new(self.scroll_state.take().position(i * ITEM_HEIGHT));
+        self.select(i);
     }
 
     pub fn next_column(&mut self) {
@@ -211,10 +1159,11 @@ impl App {
     }
 }
 
-fn constraint_len_calculator(items: &VecDeque<ProcessExecution>) -> LongestItenLens {
+/// The column widths fitting `items`, the rows on screen.
+pub(crate) fn constraint_len_calculator(items: &[&ProcessExecution]) -> LongestItenLens {
     let timestamp_len = items
         .iter()
-        .map(|d| UnicodeWidthStr::width(d.timestamp.to_string().as_str()))
+        .map(|d| UnicodeWidthStr::width(format_time(d.timestamp).as_str()))
         .max()
         .unwrap_or(0);
     let pid_len = 6;
@@ -233,6 +1182,37 @@ fn constraint_len_calculator(items: &VecDeque<ProcessExecution>) -> LongestItenL
         .max()
         .unwrap_or(0);
 
+    let ret = items
+        .iter()
+        .map(|d| d.ret.map(errno::describe_ret).unwrap_or_default().len())
+        .max()
+        .unwrap_or(0);
+
+    let exit = items
+        .iter()
+        .map(|d| d.describe_exit().len())
+        .max()
+        .unwrap_or(0);
+
+    // Durations of running processes grow, leave room for the longer formats.
+    let duration = 8;
+
+    let gid = items
+        .iter()
+        .map(|d| d.gid.map(|gid| gid.to_string().len()).unwrap_or_default())
+        .max()
+        .unwrap_or(0);
+
+    let cwd = items
+        .iter()
+        .filter_map(|d| d.snapshot.as_ref()?.cwd.as_ref())
+        .map(|cwd| UnicodeWidthStr::width(cwd.display().to_string().as_str()))
+        .max()
+        .unwrap_or(0);
+
+    // Container ids are shortened.
+    let container = 12;
+
     let args = items
         .iter()
         .map(|d| UnicodeWidthStr::width(d.args.as_str()))
@@ -245,7 +1225,12 @@ fn constraint_len_calculator(items: &VecDeque<ProcessExecution>) -> LongestItenL
         pid: pid_len as u16,
         ppid: ppid_len as u16,
         comm: comm as u16,
+        ret: ret as u16,
+        exit: exit as u16,
+        duration,
+        gid: gid as u16,
+        cwd: cwd as u16,
+        container,
         args: args as u16,
     }
-
 }
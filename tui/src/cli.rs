@@ -0,0 +1,116 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::output::Format;
+use crate::source::{
+    recording::{parse_offset, Speed},
+    Backend, KernelFilters,
+};
+
+/// Watch process executions as they happen.
+#[derive(Debug, Clone, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Where process executions are read from.
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub backend: Backend,
+
+    /// Capture file read by the replay backend.
+    #[arg(long, required_if_eq("backend", "replay"))]
+    pub replay_file: Option<PathBuf>,
+
+    /// Size in bytes of the ring buffer shared with the kernel by the aya backend.
+    ///
+    /// Rounded up to a power of two. Increase it if the footer reports dropped events.
+    #[arg(long, default_value_t = ebpf_common::RING_BUF_SIZE, global = true)]
+    pub ring_buffer_size: u32,
+
+    /// Only trace execs by this uid. Can be repeated.
+    #[arg(long = "uid", value_name = "UID", global = true)]
+    pub uids: Vec<u32>,
+
+    /// Ignore execs by this uid. Can be repeated.
+    #[arg(long = "exclude-uid", value_name = "UID", global = true)]
+    pub exclude_uids: Vec<u32>,
+
+    /// Only trace execs by processes with this comm. Can be repeated.
+    #[arg(long = "comm", value_name = "COMM", global = true)]
+    pub comms: Vec<String>,
+
+    /// Ignore execs by processes with this comm. Can be repeated.
+    #[arg(long = "exclude-comm", value_name = "COMM", global = true)]
+    pub exclude_comms: Vec<String>,
+
+    /// Only trace execs by this process and the processes it forks from now on.
+    #[arg(long, global = true)]
+    pub pid: Option<u32>,
+
+    /// Where the column layout and sort order are kept.
+    ///
+    /// Defaults to `$XDG_CONFIG_HOME/sysrat/config.toml`.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// How many executions are kept for scrolling back.
+    #[arg(long, default_value_t = crate::app::DEFAULT_HISTORY_SIZE, global = true)]
+    pub history_size: usize,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Write every event to a file instead of showing them, until interrupted.
+    Record {
+        /// The recording to write, replaced if it exists.
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Show a recording written by `record`.
+    Replay {
+        file: PathBuf,
+
+        /// How much faster than recorded, like `10x`, or `max` to not wait at all.
+        #[arg(long, default_value_t)]
+        speed: Speed,
+
+        /// Start this far into the recording, like `90s` or `1h30m`.
+        ///
+        /// The events before are replayed at once.
+        #[arg(long, value_parser = parse_offset)]
+        seek: Option<Duration>,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+/// The headless mode, accepted both before and after `replay`.
+#[derive(Debug, Clone, Default, Args)]
+pub struct OutputArgs {
+    /// Write the events as lines in this format instead of showing them.
+    #[arg(long, value_enum)]
+    pub output: Option<Format>,
+
+    /// Where the lines of `--output` are written, stdout by default.
+    #[arg(long, requires = "output")]
+    pub output_file: Option<PathBuf>,
+}
+
+impl Cli {
+    /// The filters requested on the command line, applied in the kernel by the aya backend.
+    pub fn kernel_filters(&self) -> KernelFilters {
+        KernelFilters {
+            uids: self.uids.clone(),
+            exclude_uids: self.exclude_uids.clone(),
+            comms: self.comms.clone(),
+            exclude_comms: self.exclude_comms.clone(),
+            pid: self.pid,
+        }
+    }
+}
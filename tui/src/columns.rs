@@ -0,0 +1,394 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+};
+
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use crate::{enrich::ProcSnapshot, Lifetime, ProcessExecution, ProcessExit};
+
+/// A column of the table, besides the row number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Timestamp,
+    User,
+    Gid,
+    Pid,
+    Ppid,
+    Comm,
+    Ret,
+    Exit,
+    Duration,
+    Cwd,
+    Container,
+    Args,
+}
+
+impl Column {
+    /// Every column, in the default order.
+    pub const ALL: [Column; 12] = [
+        Column::Timestamp,
+        Column::User,
+        Column::Gid,
+        Column::Pid,
+        Column::Ppid,
+        Column::Comm,
+        Column::Ret,
+        Column::Exit,
+        Column::Duration,
+        Column::Cwd,
+        Column::Container,
+        Column::Args,
+    ];
+
+    /// The columns shown unless configured otherwise.
+    pub const DEFAULT: [Column; 9] = [
+        Column::Timestamp,
+        Column::User,
+        Column::Pid,
+        Column::Ppid,
+        Column::Comm,
+        Column::Ret,
+        Column::Exit,
+        Column::Duration,
+        Column::Args,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Timestamp => "timestamp",
+            Column::User => "user",
+            Column::Gid => "gid",
+            Column::Pid => "pid",
+            Column::Ppid => "ppid",
+            Column::Comm => "comm",
+            Column::Ret => "ret",
+            Column::Exit => "exit",
+            Column::Duration => "duration",
+            Column::Cwd => "cwd",
+            Column::Container => "container",
+            Column::Args => "args",
+        }
+    }
+
+    /// Where the column is in [`ProcessExecution::ref_array`], if it is there.
+    pub fn ref_index(self) -> Option<usize> {
+        match self {
+            Column::Timestamp => Some(0),
+            Column::User => Some(1),
+            Column::Pid => Some(2),
+            Column::Ppid => Some(3),
+            Column::Comm => Some(4),
+            Column::Ret => Some(5),
+            Column::Exit => Some(6),
+            Column::Duration => Some(7),
+            Column::Args => Some(8),
+            Column::Gid | Column::Cwd | Column::Container => None,
+        }
+    }
+
+    /// The cell of `process`, given its [`ProcessExecution::ref_array`].
+    pub fn value(self, process: &ProcessExecution, ref_array: &[String; 9]) -> String {
+        if let Some(index) = self.ref_index() {
+            return ref_array[index].clone();
+        }
+        let snapshot = process.snapshot.as_deref();
+        match self {
+            Column::Gid => process.gid.map(|gid| gid.to_string()).unwrap_or_default(),
+            Column::Cwd => snapshot
+                .and_then(|s| s.cwd.as_ref())
+                .map(|cwd| cwd.display().to_string())
+                .unwrap_or_default(),
+            Column::Container => snapshot.and_then(container_id).unwrap_or_default(),
+            _ => unreachable!("{self} is in the ref array"),
+        }
+    }
+
+    /// Orders rows by the column, ascending.
+    pub fn compare(self, a: &ProcessExecution, b: &ProcessExecution) -> Ordering {
+        match self {
+            Column::Timestamp => a.timestamp.cmp(&b.timestamp),
+            Column::User => a.username.cmp(&b.username),
+            Column::Gid => a.gid.cmp(&b.gid),
+            Column::Pid => a.pid.cmp(&b.pid),
+            Column::Ppid => a.ppid.cmp(&b.ppid),
+            Column::Comm => a.comm.cmp(&b.comm),
+            Column::Ret => a.ret.cmp(&b.ret),
+            Column::Exit => ExitKey::of(a).cmp(&ExitKey::of(b)),
+            Column::Duration => a.duration().cmp(&b.duration()),
+            Column::Cwd => {
+                let cwd = |p: &ProcessExecution| p.snapshot.as_ref().and_then(|s| s.cwd.clone());
+                cwd(a).cmp(&cwd(b))
+            }
+            Column::Container => {
+                let id = |p: &ProcessExecution| p.snapshot.as_deref().and_then(container_id);
+                id(a).cmp(&id(b))
+            }
+            Column::Args => a.args.cmp(&b.args),
+        }
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What the exit column sorts by: unknown, running and replaced first, then the exit codes and
+/// the signals by number.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ExitKey {
+    Unknown,
+    Running,
+    Replaced,
+    Code(i32),
+    Signal(i32),
+}
+
+impl ExitKey {
+    fn of(process: &ProcessExecution) -> Self {
+        match process.lifetime {
+            Lifetime::Unknown => ExitKey::Unknown,
+            Lifetime::Running => ExitKey::Running,
+            Lifetime::Replaced(_) => ExitKey::Replaced,
+            Lifetime::Exited(ProcessExit {
+                signal: Some(signal),
+                ..
+            }) => ExitKey::Signal(signal),
+            Lifetime::Exited(exit) => ExitKey::Code(exit.code.unwrap_or_default()),
+        }
+    }
+}
+
+/// How the timestamp column shows the time of an exec, cycled with `T`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeDisplay {
+    /// The local date and time.
+    #[default]
+    Absolute,
+    /// Since the first exec seen.
+    Relative,
+    /// Since the exec in the row above.
+    Delta,
+}
+
+impl TimeDisplay {
+    pub fn next(self) -> Self {
+        match self {
+            TimeDisplay::Absolute => TimeDisplay::Relative,
+            TimeDisplay::Relative => TimeDisplay::Delta,
+            TimeDisplay::Delta => TimeDisplay::Absolute,
+        }
+    }
+
+    /// Shows `time`, relative to `start` or to `previous` when not absolute.
+    ///
+    /// The delta of the first row is empty.
+    pub fn format(
+        self,
+        time: DateTime<Local>,
+        start: Option<DateTime<Local>>,
+        previous: Option<DateTime<Local>>,
+    ) -> String {
+        let since = match self {
+            TimeDisplay::Absolute => return format_time(time),
+            TimeDisplay::Relative => start,
+            TimeDisplay::Delta => previous,
+        };
+        since
+            .map(|since| format_offset(time - since))
+            .unwrap_or_default()
+    }
+}
+
+/// The local date and time down to the nanosecond, with the UTC offset as in the detail pane.
+pub fn format_time(time: DateTime<Local>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S%.9f %:z").to_string()
+}
+
+/// A signed number of seconds down to the nanosecond, like `+1.250000000s`.
+fn format_offset(offset: TimeDelta) -> String {
+    let sign = if offset < TimeDelta::zero() { '-' } else { '+' };
+    let offset = offset.abs();
+    format!(
+        "{sign}{}.{:09}s",
+        offset.num_seconds(),
+        offset.subsec_nanos()
+    )
+}
+
+/// The order of the columns and which of them are shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnLayout {
+    /// Every column exactly once.
+    columns: Vec<(Column, bool)>,
+}
+
+impl Default for ColumnLayout {
+    fn default() -> Self {
+        Self::new(&Column::DEFAULT)
+    }
+}
+
+impl ColumnLayout {
+    /// Shows `visible` in that order, followed by the other columns hidden.
+    pub fn new(visible: &[Column]) -> Self {
+        let mut columns = Vec::with_capacity(Column::ALL.len());
+        for &column in visible {
+            if !columns.iter().any(|(c, _)| *c == column) {
+                columns.push((column, true));
+            }
+        }
+        for column in Column::ALL {
+            if !columns.iter().any(|(c, _)| *c == column) {
+                columns.push((column, false));
+            }
+        }
+        Self { columns }
+    }
+
+    /// All columns in order, with whether they are shown.
+    pub fn all(&self) -> &[(Column, bool)] {
+        &self.columns
+    }
+
+    pub fn visible(&self) -> impl Iterator<Item = Column> + '_ {
+        self.columns
+            .iter()
+            .filter(|(_, visible)| *visible)
+            .map(|(column, _)| *column)
+    }
+
+    /// Shows or hides the column at `index` of [`ColumnLayout::all`].
+    pub fn toggle(&mut self, index: usize) {
+        if let Some((_, visible)) = self.columns.get_mut(index) {
+            *visible = !*visible;
+        }
+    }
+
+    /// Swaps the column at `index` with its neighbour `delta` away, returning where it is now.
+    pub fn move_column(&mut self, index: usize, delta: isize) -> usize {
+        let Some(target) = index
+            .checked_add_signed(delta)
+            .filter(|&target| target < self.columns.len())
+        else {
+            return index;
+        };
+        self.columns.swap(index, target);
+        target
+    }
+}
+
+/// The id of the container the process runs in, shortened like `docker ps` does.
+///
+/// Recognises the cgroup paths of docker, podman, containerd and CRI-O.
+pub fn container_id(snapshot: &ProcSnapshot) -> Option<String> {
+    let cgroup = snapshot.cgroup.as_deref()?;
+    cgroup
+        .lines()
+        .flat_map(|line| line.rsplit(['/', ':']))
+        .find_map(|segment| {
+            let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+            let id = segment.rsplit(['-', ':']).next()?;
+            let is_id = id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit());
+            is_id.then(|| id[..12].to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_column_layout() {
+        let mut layout = ColumnLayout::new(&[Column::Pid, Column::Args, Column::Pid]);
+        assert_eq!(
+            layout.visible().collect::<Vec<_>>(),
+            [Column::Pid, Column::Args]
+        );
+        assert_eq!(layout.all().len(), Column::ALL.len());
+
+        layout.toggle(2);
+        assert_eq!(layout.move_column(2, -1), 1);
+        assert_eq!(layout.move_column(0, -1), 0);
+        assert_eq!(
+            layout.visible().collect::<Vec<_>>(),
+            [Column::Pid, Column::Timestamp, Column::Args]
+        );
+    }
+
+    #[test]
+    fn test_exit_order() {
+        let exited = |code, signal| ProcessExecution {
+            lifetime: Lifetime::Exited(ProcessExit {
+                pid: 1,
+                start_time: None,
+                timestamp: Local::now(),
+                code,
+                signal,
+                duration: std::time::Duration::ZERO,
+            }),
+            ..Default::default()
+        };
+        let mut processes = [
+            exited(None, Some(9)),
+            exited(Some(10), None),
+            exited(None, Some(2)),
+            exited(Some(9), None),
+            ProcessExecution {
+                lifetime: Lifetime::Running,
+                ..Default::default()
+            },
+        ];
+        processes.sort_by(|a, b| Column::Exit.compare(a, b));
+        let exits = processes
+            .iter()
+            .map(|p| p.describe_exit())
+            .collect::<Vec<_>>();
+        assert_eq!(exits, ["running", "9", "10", "SIGINT", "SIGKILL"]);
+    }
+
+    #[test]
+    fn test_container_id() {
+        let id = "4f0c1e3e5b2d9a8f7c6b5a4d3e2f1a0b9c8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f";
+        let snapshot = |cgroup: String| ProcSnapshot {
+            cgroup: Some(cgroup),
+            ..Default::default()
+        };
+        for cgroup in [
+            format!("0::/system.slice/docker-{id}.scope"),
+            format!("12:pids:/docker/{id}\n0::/"),
+            format!("0::/kubepods.slice/kubepods-pod1.slice/cri-containerd-{id}.scope"),
+            format!("0::/machine.slice/libpod-{id}.scope/container"),
+        ] {
+            assert_eq!(
+                container_id(&snapshot(cgroup)).as_deref(),
+                Some("4f0c1e3e5b2d")
+            );
+        }
+        assert_eq!(container_id(&snapshot("0::/user.slice".to_string())), None);
+    }
+
+    #[test]
+    fn test_time_display() {
+        let start = Local.timestamp_nanos(1_742_937_361_000_000_000);
+        let time = start + TimeDelta::nanoseconds(1_250_000_001);
+        let absolute = TimeDisplay::Absolute.format(time, Some(start), None);
+        assert!(absolute.contains(":02.250000001 "), "{absolute}");
+        assert_eq!(
+            TimeDisplay::Relative.format(time, Some(start), None),
+            "+1.250000001s"
+        );
+        assert_eq!(
+            TimeDisplay::Delta.format(start, None, Some(time)),
+            "-1.250000001s"
+        );
+        assert_eq!(TimeDisplay::Delta.format(time, Some(start), None), "");
+    }
+}
@@ -0,0 +1,94 @@
+//! Settings changed in the TUI that are kept across runs.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::columns::{Column, ColumnLayout, TimeDisplay};
+
+/// The content of `config.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The columns shown, in order. The default ones if empty.
+    pub columns: Vec<Column>,
+    pub sort: Option<Sort>,
+    pub time: TimeDisplay,
+}
+
+/// How the rows are ordered, by time of exec if not set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sort {
+    pub column: Column,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/sysrat/config.toml`, or the same under `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("sysrat").join("config.toml"))
+    }
+
+    /// Reads the config at `path`, the default config if there is no such file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                toml::from_str(&content).with_context(|| format!("invalid {}", path.display()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn column_layout(&self) -> ColumnLayout {
+        if self.columns.is_empty() {
+            ColumnLayout::default()
+        } else {
+            ColumnLayout::new(&self.columns)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_roundtrip() {
+        let config = Config {
+            columns: vec![Column::Pid, Column::Container, Column::Args],
+            sort: Some(Sort {
+                column: Column::Duration,
+                descending: true,
+            }),
+            time: TimeDisplay::Delta,
+        };
+        let content = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&content).unwrap(), config);
+
+        let config = toml::from_str::<Config>("columns = [\"comm\", \"args\"]").unwrap();
+        assert_eq!(
+            config.column_layout().visible().collect::<Vec<_>>(),
+            [Column::Comm, Column::Args]
+        );
+        assert!(toml::from_str::<Config>("columns = [\"nope\"]").is_err());
+    }
+}
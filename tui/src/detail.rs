@@ -0,0 +1,182 @@
+use crate::{errno, format_duration, process_service::RenderedProcess, ProcessExecution};
+
+/// Everything known about a row, shown in the pane opened with `Enter`.
+///
+/// Taken when the pane is opened. Once the process exited, the `/proc` parts come from the
+/// snapshot taken at exec time, if any.
+#[derive(Debug, Clone)]
+pub struct ProcessDetail {
+    pub title: String,
+    /// Labels and values, which may span several lines.
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl ProcessDetail {
+    /// `ancestors` are pids and names, starting with the parent.
+    pub fn new(
+        process: &ProcessExecution,
+        running: Option<RenderedProcess>,
+        ancestors: &[(u32, String)],
+    ) -> Self {
+        let mut fields = Vec::new();
+        let mut field = |label, value: String| {
+            if !value.is_empty() {
+                fields.push((label, value));
+            }
+        };
+
+        let snapshot = process.snapshot.as_deref();
+        let exe = running
+            .as_ref()
+            .and_then(|r| r.exe.clone())
+            .or_else(|| snapshot.and_then(|s| s.exe.clone()))
+            .or_else(|| process.path.clone());
+        field(
+            "exe",
+            exe.map(|p| p.display().to_string()).unwrap_or_default(),
+        );
+        match running.as_ref().filter(|r| !r.cmd.is_empty()) {
+            Some(running) => field(
+                "argv",
+                running
+                    .cmd
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| format!("[{i}] {arg}"))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            None => field("args", process.args.clone()),
+        }
+        field(
+            "cwd",
+            running
+                .as_ref()
+                .and_then(|r| r.cwd.as_ref())
+                .or_else(|| snapshot.and_then(|s| s.cwd.as_ref()))
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+        );
+        let user = running
+            .as_ref()
+            .and_then(|r| r.user.clone())
+            .or_else(|| process.username.clone());
+        field("user", user.unwrap_or_default());
+        field(
+            "group",
+            running
+                .as_ref()
+                .and_then(|r| r.group.clone())
+                .unwrap_or_default(),
+        );
+        field(
+            "parents",
+            ancestors
+                .iter()
+                .map(|(pid, name)| format!("{name} ({pid})"))
+                .collect::<Vec<_>>()
+                .join(" < "),
+        );
+        field(
+            "executed",
+            process
+                .timestamp
+                .format("%Y-%m-%d %H:%M:%S%.9f %:z")
+                .to_string(),
+        );
+        field(
+            "started",
+            running
+                .as_ref()
+                .and_then(|r| r.start_time)
+                .map(|t| t.to_string())
+                .unwrap_or_default(),
+        );
+        field(
+            "ret",
+            process.ret.map(errno::describe_ret).unwrap_or_default(),
+        );
+        field("exit", process.describe_exit());
+        field(
+            "duration",
+            process.duration().map(format_duration).unwrap_or_default(),
+        );
+        if let Some(running) = &running {
+            field("status", running.status.clone());
+            field(
+                "memory",
+                format!(
+                    "{} resident, {} virtual",
+                    format_bytes(running.memory),
+                    format_bytes(running.virtual_memory)
+                ),
+            );
+            field(
+                "disk",
+                format!(
+                    "{} read, {} written",
+                    format_bytes(running.disk_read),
+                    format_bytes(running.disk_written)
+                ),
+            );
+            field("environment", running.env.join("\n"));
+        } else {
+            field("status", "not running anymore".to_string());
+        }
+        if let Some(snapshot) = snapshot {
+            field("state at exec", snapshot.state.clone().unwrap_or_default());
+            field("cgroup", snapshot.cgroup.clone().unwrap_or_default());
+            if running.is_none() {
+                field("environment", snapshot.environ.join("\n"));
+            }
+        }
+        if let Some(raw) = process.raw.as_deref() {
+            let header = &raw.header;
+            field(
+                "syscall",
+                if header.syscall == ebpf_common::SYSCALL_EXECVEAT {
+                    format!(
+                        "execveat({}, {:?}, {:#x})",
+                        header.dirfd, raw.filename, header.at_flags
+                    )
+                } else {
+                    format!("execve({:?})", raw.filename)
+                },
+            );
+            if running.is_none() && snapshot.is_none() {
+                field("environment", raw.envs.join("\n"));
+            }
+        }
+
+        Self {
+            title: format!("{} ({})", process.comm, process.pid),
+            fields,
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
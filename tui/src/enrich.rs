@@ -0,0 +1,228 @@
+//! Snapshots of `/proc/<pid>` taken right after an exec, before short-lived processes are gone.
+//!
+//! The [`Enricher`] reads them on its own thread so that slow reads never hold up the events, and
+//! delivers them as [`AppEvent::ProcessEnriched`]. It also looks up the parents of rows whose exec
+//! was not seen, delivered as [`AppEvent::ParentFound`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use crate::{
+    event::{AppEvent, Event},
+    process_service::ProcessService,
+    ProcessExecution, ProcessKey,
+};
+
+/// How many processes the [`Enricher`] remembers snapshots of.
+const CACHE_SIZE: usize = 4096;
+
+/// What `/proc/<pid>` showed right after an exec.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcSnapshot {
+    /// When the process started, in clock ticks since boot. Stays the same across execs.
+    pub start_time: u64,
+    pub cwd: Option<PathBuf>,
+    pub exe: Option<PathBuf>,
+    pub environ: Vec<String>,
+    /// The content of `/proc/<pid>/cgroup`, one line per hierarchy.
+    pub cgroup: Option<String>,
+    /// The `State` line of `/proc/<pid>/status`, e.g. `R (running)`.
+    pub state: Option<String>,
+}
+
+impl ProcSnapshot {
+    /// Reads the snapshot of `pid` from the procfs mounted at `proc_root`.
+    ///
+    /// Fails if the process is gone, the other files are optional since reading them needs more
+    /// privileges.
+    pub fn read(proc_root: &Path, pid: u32) -> io::Result<Self> {
+        let dir = proc_root.join(pid.to_string());
+        let start_time = parse_start_time(&fs::read_to_string(dir.join("stat"))?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed stat"))?;
+        let environ = fs::read(dir.join("environ"))
+            .map(|environ| {
+                environ
+                    .split(|&b| b == 0)
+                    .filter(|var| !var.is_empty())
+                    .map(|var| String::from_utf8_lossy(var).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            start_time,
+            cwd: fs::read_link(dir.join("cwd")).ok(),
+            exe: fs::read_link(dir.join("exe")).ok(),
+            environ,
+            cgroup: fs::read_to_string(dir.join("cgroup"))
+                .ok()
+                .map(|cgroup| cgroup.trim_end().to_string()),
+            state: fs::read_to_string(dir.join("status"))
+                .ok()
+                .and_then(|status| parse_state(&status)),
+        })
+    }
+}
+
+/// The start time of the process `pid` as `/proc` tells it, see [`ProcessKey::start_time`].
+pub fn read_start_time(pid: u32) -> Option<u64> {
+    parse_start_time(&fs::read_to_string(format!("/proc/{pid}/stat")).ok()?)
+}
+
+/// Converts a start time in nanoseconds since boot to clock ticks like
+/// [`ProcessKey::start_time`], `None` for 0, which backends report when they could not tell.
+pub fn start_time_from_nanos(nanos: u64) -> Option<u64> {
+    // SAFETY: sysconf has no preconditions.
+    let ticks_per_sec = match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    };
+    // Truncated like the kernel does for /proc.
+    (nanos != 0).then(|| nanos / (1_000_000_000 / ticks_per_sec))
+}
+
+/// The `starttime` field of `/proc/<pid>/stat`.
+fn parse_start_time(stat: &str) -> Option<u64> {
+    // The comm in parentheses may itself contain spaces and parentheses.
+    let (_, fields) = stat.rsplit_once(')')?;
+    // `starttime` is the 22nd field, the fields after the comm start with the 3rd.
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+fn parse_state(status: &str) -> Option<String> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("State:"))
+        .map(|state| state.trim().to_string())
+}
+
+/// What the enrichment thread is asked for.
+#[derive(Debug)]
+enum Job {
+    Snapshot(Box<ProcessExecution>),
+    Parent(ProcessKey),
+}
+
+/// Hands executions and parents to look up to the enrichment thread.
+#[derive(Debug, Clone)]
+pub struct Enricher {
+    sender: mpsc::Sender<Job>,
+}
+
+impl Enricher {
+    /// Spawns the thread reading `/proc` and sending the snapshots and parents to `events`.
+    pub fn spawn(events: mpsc::Sender<Event>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut cache = SnapshotCache::default();
+            let mut process_service = ProcessService::new();
+            for job in receiver {
+                let event = match job {
+                    Job::Snapshot(process) => {
+                        let Some(snapshot) = cache.snapshot(&process) else {
+                            continue;
+                        };
+                        AppEvent::ProcessEnriched {
+                            key: process.key(),
+                            timestamp: process.timestamp,
+                            snapshot,
+                        }
+                    }
+                    Job::Parent(key) => {
+                        let Some(parent) = process_service.running_process(key) else {
+                            continue;
+                        };
+                        AppEvent::ParentFound { key, parent }
+                    }
+                };
+                if events.send(Event::App(Box::new(event))).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    /// Queues `process` to be enriched, it is ignored if the thread is gone.
+    pub fn enrich(&self, process: &ProcessExecution) {
+        let _ = self.sender.send(Job::Snapshot(Box::new(process.clone())));
+    }
+
+    /// Queues looking up the running parent `key`, delivered as [`AppEvent::ParentFound`] if it
+    /// is still running.
+    pub fn look_up_parent(&self, key: ProcessKey) {
+        let _ = self.sender.send(Job::Parent(key));
+    }
+}
+
+/// The latest snapshot of each process, by pid and start time.
+#[derive(Debug, Default)]
+struct SnapshotCache {
+    snapshots: HashMap<(u32, u64), Arc<ProcSnapshot>>,
+    /// Insertion order, to forget the oldest processes first.
+    order: VecDeque<(u32, u64)>,
+}
+
+impl SnapshotCache {
+    /// Reads the snapshot of the process that executed, `None` if it is gone already, or its
+    /// pid already belongs to another process.
+    ///
+    /// A failed exec leaves the process as it was, so the cached snapshot is reused.
+    fn snapshot(&mut self, process: &ProcessExecution) -> Option<Arc<ProcSnapshot>> {
+        let proc_root = Path::new("/proc");
+        if process.failed() {
+            let start_time = process
+                .start_time
+                .or_else(|| read_start_time(process.pid))?;
+            if let Some(snapshot) = self.snapshots.get(&(process.pid, start_time)) {
+                return Some(snapshot.clone());
+            }
+        }
+        let snapshot = Arc::new(ProcSnapshot::read(proc_root, process.pid).ok()?);
+        if !process
+            .key()
+            .matches(ProcessKey::new(process.pid, Some(snapshot.start_time)))
+        {
+            return None;
+        }
+        let key = (process.pid, snapshot.start_time);
+        if self.snapshots.insert(key, snapshot.clone()).is_none() {
+            self.order.push_back(key);
+            if self.order.len() > CACHE_SIZE {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.snapshots.remove(&oldest);
+                }
+            }
+        }
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc() {
+        let stat = "4242 (tmux: server (1)) S 1 4242 4242 0 -1 4194624 1234 0 0 0 12 5 0 0 20 0 1 \
+                    0 987654 12345678 900 18446744073709551615";
+        assert_eq!(parse_start_time(stat), Some(987654));
+        assert_eq!(parse_start_time("4242 (sh"), None);
+
+        let status = "Name:\tsh\nUmask:\t0022\nState:\tS (sleeping)\nTgid:\t4242\n";
+        assert_eq!(parse_state(status).as_deref(), Some("S (sleeping)"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_read_snapshot() {
+        let snapshot = ProcSnapshot::read(Path::new("/proc"), std::process::id()).unwrap();
+        assert!(snapshot.start_time > 0);
+        assert_eq!(snapshot.cwd, std::env::current_dir().ok());
+        assert_eq!(snapshot.exe, std::env::current_exe().ok());
+    }
+}
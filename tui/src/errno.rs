@@ -0,0 +1,50 @@
+/// Symbolic name of a Linux errno value, for the errors `execve` commonly returns.
+pub fn errno_name(errno: i32) -> Option<&'static str> {
+    Some(match errno {
+        1 => "EPERM",
+        2 => "ENOENT",
+        4 => "EINTR",
+        5 => "EIO",
+        7 => "E2BIG",
+        8 => "ENOEXEC",
+        9 => "EBADF",
+        11 => "EAGAIN",
+        12 => "ENOMEM",
+        13 => "EACCES",
+        14 => "EFAULT",
+        20 => "ENOTDIR",
+        21 => "EISDIR",
+        22 => "EINVAL",
+        23 => "ENFILE",
+        24 => "EMFILE",
+        26 => "ETXTBSY",
+        36 => "ENAMETOOLONG",
+        40 => "ELOOP",
+        80 => "ELIBBAD",
+        _ => return None,
+    })
+}
+
+/// Human readable form of an exec return value: `ok`, or the negated errno by name.
+pub fn describe_ret(ret: i32) -> String {
+    if ret >= 0 {
+        return "ok".to_string();
+    }
+    match errno_name(-ret) {
+        Some(name) => name.to_string(),
+        None => format!("errno {}", -ret),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_ret() {
+        assert_eq!(describe_ret(0), "ok");
+        assert_eq!(describe_ret(-2), "ENOENT");
+        assert_eq!(describe_ret(-13), "EACCES");
+        assert_eq!(describe_ret(-1000), "errno 1000");
+    }
+}
@@ -1,12 +1,16 @@
 use color_eyre::eyre::WrapErr;
 use ratatui::crossterm::event::{self, Event as CrosstermEvent};
 use std::{
-    sync::mpsc,
+    sync::{mpsc, Arc},
     thread,
     time::{Duration, Instant},
 };
 
-use crate::ProcessExecution;
+use chrono::{DateTime, Local};
+
+use crate::{
+    enrich::ProcSnapshot, source::Backend, ProcessExecution, ProcessExit, ProcessFork, ProcessKey,
+};
 
 /// The frequency at which tick events are emitted.
 const TICK_FPS: f64 = 30.0;
@@ -26,8 +30,9 @@ pub enum Event {
     Crossterm(CrosstermEvent),
     /// Application events.
     ///
-    /// Use this event to emit custom events that are specific to your application.
-    App(AppEvent),
+    /// Use this event to emit custom events that are specific to your application. Boxed as an
+    /// exec is much larger than the other events.
+    App(Box<AppEvent>),
 }
 
 /// Application events.
@@ -38,6 +43,50 @@ pub enum AppEvent {
     /// Append new process to the table
     NewProcess(ProcessExecution),
 
+    /// Mark the rows of an exited process
+    ProcessExited(ProcessExit),
+
+    /// Add a forked process to the process tree
+    ProcessForked(ProcessFork),
+
+    /// Attach what `/proc` showed to the row of the exec of `key` at `timestamp`
+    ProcessEnriched {
+        key: ProcessKey,
+        timestamp: DateTime<Local>,
+        snapshot: Arc<ProcSnapshot>,
+    },
+
+    /// `parent` was running before its exec could be seen, it is the parent `key` of an exec
+    ParentFound {
+        key: ProcessKey,
+        parent: ProcessExecution,
+    },
+
+    /// The backend lost events, `total` so far as of `timestamp`
+    EventsDropped {
+        total: u64,
+        timestamp: DateTime<Local>,
+    },
+
+    /// The backend printed something that could not be parsed, `total` lines so far
+    MalformedOutput { total: u64, error: String },
+
+    /// The backend started, or started again after a failure
+    BackendStarted(Backend),
+
+    /// The backend ran out of events
+    BackendExited(Backend),
+
+    /// The backend failed, `error` may end with what it printed to stderr
+    BackendFailed { backend: Backend, error: String },
+
+    /// The backend is opened again after `delay`, `attempt` failures in a row
+    BackendRestarting {
+        backend: Backend,
+        attempt: u32,
+        delay: Duration,
+    },
+
     /// Print the diagnostic information
     Print(String),
 
@@ -45,6 +94,43 @@ pub enum AppEvent {
     Quit,
 }
 
+impl AppEvent {
+    /// Whether the event adds or updates rows, as opposed to the state around them.
+    pub fn changes_rows(&self) -> bool {
+        matches!(
+            self,
+            AppEvent::NewProcess(_)
+                | AppEvent::ProcessExited(_)
+                | AppEvent::ProcessForked(_)
+                | AppEvent::ProcessEnriched { .. }
+                | AppEvent::ParentFound { .. }
+        )
+    }
+
+    /// What a headless mode prints to stderr about the event, if anything.
+    pub fn notice(&self) -> Option<String> {
+        match self {
+            AppEvent::MalformedOutput { error, .. } => {
+                Some(format!("could not parse the backend output: {error}"))
+            }
+            AppEvent::BackendExited(backend) => Some(format!("{backend} exited")),
+            AppEvent::BackendFailed { backend, error } => {
+                Some(format!("{backend} failed: {error}"))
+            }
+            AppEvent::BackendRestarting {
+                backend,
+                attempt,
+                delay,
+            } => Some(format!(
+                "restarting {backend} in {}s, attempt {attempt}",
+                delay.as_secs()
+            )),
+            AppEvent::Print(msg) => Some(msg.clone()),
+            _ => None,
+        }
+    }
+}
+
 /// Terminal event handler.
 #[derive(Debug)]
 pub struct EventHandler {
@@ -54,14 +140,20 @@ pub struct EventHandler {
     receiver: mpsc::Receiver<Event>,
 }
 
-impl EventHandler {
-    /// Constructs a new instance of [`EventHandler`] and spawns a new thread to handle events.
-    pub fn new() -> Self {
+impl Default for EventHandler {
+    fn default() -> Self {
         let (sender, receiver) = mpsc::channel();
         let actor = EventThread::new(sender.clone());
         thread::spawn(|| actor.run());
         Self { sender, receiver }
     }
+}
+
+impl EventHandler {
+    /// Constructs a new instance of [`EventHandler`] and spawns a new thread to handle events.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
     /// Receives an event from the sender.
     ///
@@ -76,6 +168,11 @@ impl EventHandler {
         Ok(self.receiver.recv()?)
     }
 
+    /// Receives an event if one is pending, without blocking.
+    pub fn try_next(&self) -> Option<Event> {
+        self.receiver.try_recv().ok()
+    }
+
     /// Queue an app event to be sent to the event receiver.
     ///
     /// This is useful for sending events to the event handler which will be processed by the next
@@ -83,7 +180,7 @@ impl EventHandler {
     pub fn send(&mut self, app_event: AppEvent) {
         // Ignore the result as the reciever cannot be dropped while this struct still has a
         // reference to it
-        let _ = self.sender.send(Event::App(app_event));
+        let _ = self.sender.send(Event::App(Box::new(app_event)));
     }
 }
 
@@ -0,0 +1,119 @@
+use std::fmt::{self, Display};
+
+use regex::Regex;
+
+use crate::ProcessExecution;
+
+/// A column a [`ProcessFilter`] can be scoped to with a `name:` prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Comm,
+    Args,
+    User,
+}
+
+impl Field {
+    const ALL: [Field; 3] = [Field::Comm, Field::Args, Field::User];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Comm => "comm",
+            Field::Args => "args",
+            Field::User => "user",
+        }
+    }
+
+    fn value(self, process: &ProcessExecution) -> &str {
+        match self {
+            Field::Comm => &process.comm,
+            Field::Args => &process.args,
+            Field::User => process.username.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// A regex matched against the rows of the table, typed into the `/` filter bar.
+///
+/// `comm:sh`, `args:^-c` or `user:root` only look at that column, anything else matches if any
+/// of them does.
+#[derive(Debug, Clone)]
+pub struct ProcessFilter {
+    field: Option<Field>,
+    regex: Regex,
+}
+
+impl ProcessFilter {
+    pub fn parse(input: &str) -> Result<Self, regex::Error> {
+        let (field, pattern) = Field::ALL
+            .into_iter()
+            .find_map(|field| {
+                input
+                    .strip_prefix(field.name())
+                    .and_then(|rest| rest.strip_prefix(':'))
+                    .map(|pattern| (Some(field), pattern))
+            })
+            .unwrap_or((None, input));
+        Ok(Self {
+            field,
+            regex: Regex::new(pattern)?,
+        })
+    }
+
+    pub fn matches(&self, process: &ProcessExecution) -> bool {
+        match self.field {
+            Some(field) => self.regex.is_match(field.value(process)),
+            None => Field::ALL
+                .into_iter()
+                .any(|field| self.regex.is_match(field.value(process))),
+        }
+    }
+}
+
+impl Display for ProcessFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(field) = self.field {
+            write!(f, "{}:", field.name())?;
+        }
+        f.write_str(self.regex.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(comm: &str, args: &str, user: &str) -> ProcessExecution {
+        ProcessExecution {
+            comm: comm.to_string(),
+            args: args.to_string(),
+            username: Some(user.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_process_filter() {
+        let sh = process("sh", "-c ls", "root");
+        let cargo = process("cargo", "build --release", "alice");
+
+        let filter = ProcessFilter::parse("comm:^sh$").unwrap();
+        assert!(filter.matches(&sh));
+        assert!(!filter.matches(&cargo));
+
+        let filter = ProcessFilter::parse("user:alice").unwrap();
+        assert!(!filter.matches(&sh));
+        assert!(filter.matches(&cargo));
+
+        // Unscoped patterns look at every column.
+        let filter = ProcessFilter::parse("ls|alice").unwrap();
+        assert!(filter.matches(&sh));
+        assert!(filter.matches(&cargo));
+
+        // Unknown prefixes are part of the pattern.
+        let filter = ProcessFilter::parse("pid:1").unwrap();
+        assert!(!filter.matches(&sh));
+        assert_eq!(filter.to_string(), "pid:1");
+
+        assert!(ProcessFilter::parse("args:(").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local};
+
+use crate::ProcessExecution;
+
+/// What executions have in common to be merged into one [`ProcessGroup`].
+///
+/// The comm and args, with runs of digits replaced so that e.g. `sleep 1` and `sleep 2` or
+/// numbered temporary files end up in the same group.
+pub fn fingerprint(process: &ProcessExecution) -> String {
+    let mut fingerprint = String::with_capacity(process.comm.len() + process.args.len() + 1);
+    for c in process
+        .comm
+        .chars()
+        .chain([' '])
+        .chain(process.args.chars())
+    {
+        if !c.is_ascii_digit() {
+            fingerprint.push(c);
+        } else if !fingerprint.ends_with('#') {
+            fingerprint.push('#');
+        }
+    }
+    fingerprint
+}
+
+/// Executions sharing a [`fingerprint`], oldest first.
+#[derive(Debug, Clone)]
+pub struct ProcessGroup<'a> {
+    pub fingerprint: String,
+    pub members: Vec<&'a ProcessExecution>,
+    /// Where the members were among the processes grouped.
+    pub positions: Vec<usize>,
+}
+
+impl<'a> ProcessGroup<'a> {
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// The most recent member.
+    pub fn last(&self) -> &'a ProcessExecution {
+        self.members.last().expect("groups are never empty")
+    }
+
+    pub fn first_seen(&self) -> DateTime<Local> {
+        self.members[0].timestamp
+    }
+}
+
+/// Groups `processes` by their [`fingerprint`].
+///
+/// The groups are ordered by their most recent member, like the rows they replace.
+pub fn group<'a>(
+    processes: impl IntoIterator<Item = &'a ProcessExecution>,
+) -> Vec<ProcessGroup<'a>> {
+    let mut groups: Vec<(usize, ProcessGroup<'a>)> = Vec::new();
+    let mut by_fingerprint = HashMap::new();
+    for (i, process) in processes.into_iter().enumerate() {
+        let fingerprint = fingerprint(process);
+        match by_fingerprint.get(&fingerprint) {
+            Some(&index) => {
+                let (last, group): &mut (usize, ProcessGroup) = &mut groups[index];
+                *last = i;
+                group.members.push(process);
+                group.positions.push(i);
+            }
+            None => {
+                by_fingerprint.insert(fingerprint.clone(), groups.len());
+                groups.push((
+                    i,
+                    ProcessGroup {
+                        fingerprint,
+                        members: vec![process],
+                        positions: vec![i],
+                    },
+                ));
+            }
+        }
+    }
+    groups.sort_by_key(|(last, _)| *last);
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, comm: &str, args: &str) -> ProcessExecution {
+        ProcessExecution {
+            pid,
+            comm: comm.to_string(),
+            args: args.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_group() {
+        let processes = [
+            process(10, "sh", "sleep 1"),
+            process(11, "make", "cc -c main.c"),
+            process(12, "sh", "sleep 25"),
+            process(13, "make", "cc -c util.c"),
+        ];
+        assert_eq!(fingerprint(&processes[2]), "sh sleep #");
+
+        let groups = group(&processes);
+        let members = groups
+            .iter()
+            .map(|g| g.members.iter().map(|p| p.pid).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(members, [vec![11], vec![10, 12], vec![13]]);
+        assert_eq!(groups[1].count(), 2);
+        assert_eq!(groups[1].positions, [0, 2]);
+        assert_eq!(groups[1].last().pid, 12);
+    }
+}
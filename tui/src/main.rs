@@ -1,61 +1,100 @@
 use crate::app::App;
 
 pub mod app;
+pub mod cli;
+pub mod columns;
+pub mod config;
+pub mod detail;
+pub mod enrich;
+pub mod errno;
 pub mod event;
+pub mod filter;
+pub mod group;
+pub mod output;
 pub mod process_service;
+pub mod process_tree;
+pub mod recording;
+pub mod signal;
+pub mod source;
+pub mod tree_view;
 pub mod ui;
 
-use chrono::NaiveDateTime;
-use colored::{Color, Colorize};
-//use colored::{Color, Colorize};
-use duct::cmd;
-use process_service::ProcessService;
+use chrono::{DateTime, Local};
+use clap::Parser;
+use color_eyre::eyre::WrapErr;
+use colored::Colorize;
+use enrich::{Enricher, ProcSnapshot};
 use std::{
     fmt::{self, Display},
-    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
 };
 
-const FORMAT: &str = "%Y %b %d %H:%M:%S";
-
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
+    let cli = cli::Cli::parse();
+    if let Some(cli::Command::Record { output }) = &cli.command {
+        return record(&cli, output);
+    }
+    // Open the backend before taking over the terminal so that a missing tool or privilege is
+    // reported on a normal screen.
+    let (mut event_source, backend) = match &cli.command {
+        Some(cli::Command::Replay {
+            file, speed, seek, ..
+        }) => {
+            if !cli.kernel_filters().is_empty() {
+                color_eyre::eyre::bail!("recordings cannot be filtered by uid, comm or pid");
+            }
+            let source =
+                source::recording::RecordingSource::new(file, *speed, seek.unwrap_or_default())
+                    .map_err(|e| color_eyre::eyre::eyre!("{e:#}"))?;
+            (
+                Box::new(source) as Box<dyn source::EventSource>,
+                source::Backend::Replay,
+            )
+        }
+        _ => (
+            source::open(&cli).map_err(|e| color_eyre::eyre::eyre!("{e:#}"))?,
+            cli.backend,
+        ),
+    };
+    let output = match &cli.command {
+        Some(cli::Command::Replay { output, .. }) if output.output.is_some() => output,
+        _ => &cli.output,
+    };
+    if let Some(format) = output.output {
+        return write_output(
+            &cli,
+            format,
+            output.output_file.as_deref(),
+            event_source,
+            backend,
+        );
+    }
+    let filter_control = event_source
+        .filter_control()
+        .map(|control| source::SharedFilterControl::new(control, cli.kernel_filters()));
+    let config_path = cli.config.clone().or_else(config::Config::default_path);
+    let config = match &config_path {
+        Some(path) => config::Config::load(path).map_err(|e| color_eyre::eyre::eyre!("{e:#}"))?,
+        None => config::Config::default(),
+    };
+
     let terminal = ratatui::init();
-    let app = App::new();
+    let app = App::new()
+        .with_kernel_filters(
+            filter_control
+                .clone()
+                .map(|control| Box::new(control) as Box<dyn source::FilterControl>),
+            cli.kernel_filters(),
+        )
+        .with_history_size(cli.history_size)
+        .with_config(&config, config_path);
     let sender = app.events.sender.clone();
 
-    let _thread = std::thread::spawn(move || {
-        // Spawn the dtrace command.
-        // Redirect stderr to stdout so we can read both from the same stream.
-        #[cfg(target_os = "macos")]
-        let child_expression = cmd!("bash", "-c", "dtrace -s ./execsnoop.d");
-        #[cfg(target_os = "linux")]
-        let child_expression = cmd!("bash", "-c", "bpftrace -q execsnoop.bpf");
-
-        let reader = child_expression.reader().unwrap();
-        let reader = BufReader::new(reader);
-
-        let process_service = ProcessService::new();
-        // Print each line as soon as it is received.
-        for line in reader.lines() {
-            match line {
-                Ok(l) => {
-                    let process = match parse_line(&l, &process_service) {
-                        Ok(p) => p,
-                        Err(_e) => {
-                            continue;
-                        }
-                    };
-                    sender
-                        .send(event::Event::App(event::AppEvent::NewProcess(process)))
-                        .unwrap();
-                }
-                Err(e) => {
-                    eprintln!("Error reading line: {}", e);
-                    break;
-                }
-            }
-        }
-    });
+    let enricher = spawn_source(&cli, backend, event_source, sender, filter_control);
+    let app = app.with_enricher(enricher);
 
     let result = app.run(terminal);
 
@@ -64,27 +103,296 @@ fn main() -> color_eyre::Result<()> {
     result
 }
 
+/// Runs the headless mode, writing the events to `path` or stdout instead of showing the TUI.
+fn write_output(
+    cli: &cli::Cli,
+    format: output::Format,
+    path: Option<&std::path::Path>,
+    event_source: Box<dyn source::EventSource>,
+    backend: source::Backend,
+) -> color_eyre::Result<()> {
+    let out: Box<dyn std::io::Write> = match path {
+        Some(path) => Box::new(
+            std::fs::File::create(path)
+                .wrap_err_with(|| format!("failed to create {}", path.display()))?,
+        ),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let (sender, receiver) = std::sync::mpsc::channel();
+    spawn_source(cli, backend, event_source, sender, None);
+    output::run(receiver, format, std::io::BufWriter::new(out))?;
+    Ok(())
+}
+
+/// Runs `sysrat record`, writing what the backend reports to `output` without the TUI.
+fn record(cli: &cli::Cli, output: &std::path::Path) -> color_eyre::Result<()> {
+    let event_source = source::open(cli).map_err(|e| color_eyre::eyre::eyre!("{e:#}"))?;
+    let file = std::fs::File::create(output)
+        .wrap_err_with(|| format!("failed to create {}", output.display()))?;
+    let (sender, receiver) = std::sync::mpsc::channel();
+    spawn_source(cli, cli.backend, event_source, sender, None);
+    eprintln!("recording to {}, Ctrl-C to stop", output.display());
+    let recorded = recording::record(receiver, std::io::BufWriter::new(file))
+        .map_err(|e| color_eyre::eyre::eyre!("{e:#}"))?;
+    eprintln!("recorded {recorded} events");
+    Ok(())
+}
+
+/// Drives `event_source` on its reader thread, enriching and restarting it unless it is a replay.
+///
+/// A restarted source takes over `filter_control` along with the filters applied through it.
+/// Returns the enricher, if any, for the app to look up parents with.
+fn spawn_source(
+    cli: &cli::Cli,
+    backend: source::Backend,
+    event_source: Box<dyn source::EventSource>,
+    sender: std::sync::mpsc::Sender<event::Event>,
+    filter_control: Option<source::SharedFilterControl>,
+) -> Option<Enricher> {
+    // Replayed processes are long gone, their pids may belong to others by now.
+    if backend == source::Backend::Replay {
+        source::spawn(backend, event_source, sender, None, None);
+        return None;
+    }
+    let enricher = Enricher::spawn(sender.clone());
+    let cli = cli.clone();
+    let reopen: source::Reopen = Box::new(move || {
+        let mut event_source = source::open(&cli)?;
+        if let (Some(shared), Some(control)) = (&filter_control, event_source.filter_control()) {
+            shared.replace(control)?;
+        }
+        Ok(event_source)
+    });
+    source::spawn(
+        backend,
+        event_source,
+        sender,
+        Some(enricher.clone()),
+        Some(reopen),
+    );
+    Some(enricher)
+}
+
 #[derive(Debug, Clone)]
 pub struct ProcessExecution {
     pub pid: u32,
+    /// See [`ProcessKey::start_time`].
+    pub start_time: Option<u64>,
     pub ppid: u32,
+    pub parent_start_time: Option<u64>,
     pub comm: String,
     pub args: String,
-    pub timestamp: NaiveDateTime,
+    pub timestamp: DateTime<Local>,
     pub username: Option<String>,
+    pub gid: Option<u32>,
+    /// Return value of the exec, if the backend reports it: 0 or a negative errno.
+    pub ret: Option<i32>,
+    /// Absolute path of the executed file, if the backend could resolve it.
+    pub path: Option<PathBuf>,
+    pub lifetime: Lifetime,
+    /// What `/proc` showed right after the exec, filled in later by the [`enrich::Enricher`].
+    pub snapshot: Option<Arc<ProcSnapshot>>,
+    /// The record of the eBPF programs, for backends that have one.
+    pub raw: Option<Arc<RawExec>>,
+}
+
+/// An exec as the eBPF programs reported it, before it was turned into a row.
+#[derive(Debug, Clone)]
+pub struct RawExec {
+    pub header: ebpf_common::ExecHeader,
+    /// The filename as passed to the syscall.
+    pub filename: String,
+    pub args: Vec<String>,
+    pub envs: Vec<String>,
+}
+
+/// What became of the process after the exec, as far as the backend knows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifetime {
+    /// The backend does not report exits, or the exec failed.
+    Unknown,
+    Running,
+    /// The process executed something else after the given time.
+    Replaced(Duration),
+    Exited(ProcessExit),
+}
+
+/// A new process, created by forking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessFork {
+    pub pid: u32,
+    pub ppid: u32,
+    pub parent_start_time: Option<u64>,
+    pub timestamp: DateTime<Local>,
+    /// The comm inherited from the parent.
+    pub comm: String,
+    /// The `CLONE_*` flags the process was created with.
+    pub clone_flags: u64,
+}
+
+/// The end of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessExit {
+    pub pid: u32,
+    pub start_time: Option<u64>,
+    pub timestamp: DateTime<Local>,
+    /// `None` if the process was killed by a signal.
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    /// Time since the last exec of the process.
+    pub duration: Duration,
+}
+
+impl ProcessFork {
+    /// The key of the child, without a start time: it is not known before the first exec. Still
+    /// matches the key of the exit, see [`ProcessKey::matches`].
+    pub fn key(&self) -> ProcessKey {
+        ProcessKey::new(self.pid, None)
+    }
+
+    pub fn parent_key(&self) -> ProcessKey {
+        ProcessKey::new(self.ppid, self.parent_start_time)
+    }
+}
+
+impl ProcessExit {
+    pub fn key(&self) -> ProcessKey {
+        ProcessKey::new(self.pid, self.start_time)
+    }
+}
+
+/// Tells a process apart from the later ones that get its pid once it is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcessKey {
+    pub pid: u32,
+    /// When the process started, in clock ticks since boot like `starttime` in
+    /// `/proc/<pid>/stat`. `None` if the backend could not tell.
+    pub start_time: Option<u64>,
+}
+
+impl ProcessKey {
+    pub fn new(pid: u32, start_time: Option<u64>) -> Self {
+        Self { pid, start_time }
+    }
+
+    /// Whether `self` and `other` may be the same process: same pid, and the same start time
+    /// unless one of them is unknown.
+    pub fn matches(self, other: Self) -> bool {
+        self.pid == other.pid
+            && match (self.start_time, other.start_time) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+    }
+}
+
+/// Tells an exec apart from the others of the same process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExecKey {
+    pub process: ProcessKey,
+    pub timestamp: DateTime<Local>,
+}
+
+/// A successful exec of `sh`, for tests to fill in what they look at.
+#[cfg(test)]
+impl Default for ProcessExecution {
+    fn default() -> Self {
+        Self {
+            pid: 1,
+            start_time: None,
+            ppid: 0,
+            parent_start_time: None,
+            comm: "sh".to_string(),
+            args: String::new(),
+            timestamp: Local::now(),
+            username: None,
+            gid: None,
+            ret: Some(0),
+            path: None,
+            lifetime: Lifetime::Unknown,
+            snapshot: None,
+            raw: None,
+        }
+    }
 }
 
 impl ProcessExecution {
-    fn ref_array(&self) -> [String; 6] {
+    pub fn key(&self) -> ProcessKey {
+        ProcessKey::new(self.pid, self.start_time)
+    }
+
+    pub fn parent_key(&self) -> ProcessKey {
+        ProcessKey::new(self.ppid, self.parent_start_time)
+    }
+
+    pub fn exec_key(&self) -> ExecKey {
+        ExecKey {
+            process: self.key(),
+            timestamp: self.timestamp,
+        }
+    }
+
+    fn ref_array(&self) -> [String; 9] {
         [
-            self.timestamp.to_string(),
+            columns::format_time(self.timestamp),
             self.username.clone().unwrap_or_default(),
             self.pid.to_string(),
             self.ppid.to_string(),
             self.comm.to_string(),
-            self.args.bold().to_string(),
+            self.ret.map(errno::describe_ret).unwrap_or_default(),
+            self.describe_exit(),
+            self.duration().map(format_duration).unwrap_or_default(),
+            self.args.clone(),
         ]
     }
+
+    /// Whether the exec is known to have failed.
+    pub fn failed(&self) -> bool {
+        self.ret.is_some_and(|ret| ret < 0)
+    }
+
+    /// Whether the process exited with a non-zero code or was killed.
+    pub fn exited_abnormally(&self) -> bool {
+        matches!(self.lifetime, Lifetime::Exited(exit) if exit.code != Some(0))
+    }
+
+    /// The exit code or signal, or whether the process is still running.
+    pub fn describe_exit(&self) -> String {
+        match self.lifetime {
+            Lifetime::Unknown => String::new(),
+            Lifetime::Running => "running".to_string(),
+            Lifetime::Replaced(_) => "exec".to_string(),
+            Lifetime::Exited(ProcessExit {
+                signal: Some(signal),
+                ..
+            }) => signal::describe_signal(signal),
+            Lifetime::Exited(exit) => exit.code.unwrap_or_default().to_string(),
+        }
+    }
+
+    /// How long the executed program ran, or has been running so far.
+    pub fn duration(&self) -> Option<Duration> {
+        match self.lifetime {
+            Lifetime::Unknown => None,
+            Lifetime::Running => (Local::now() - self.timestamp).to_std().ok(),
+            Lifetime::Replaced(duration) => Some(duration),
+            Lifetime::Exited(exit) => Some(exit.duration),
+        }
+    }
+}
+
+/// Formats `duration` with a precision that suits its magnitude, e.g. `15ms`, `2.50s`, `3m07s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else if secs >= 1 {
+        format!("{:.2}s", duration.as_secs_f64())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
 }
 
 impl Display for ProcessExecution {
@@ -92,56 +400,16 @@ impl Display for ProcessExecution {
         let ref_array = self.ref_array();
         write!(
             f,
-            "{} {} {} {} {} {}",
+            "{} {} {} {} {} {} {} {} {}",
             ref_array[0].blue(),
             ref_array[1].blue(),
             ref_array[2].blue(),
             ref_array[3].blue(),
             ref_array[4].blue(),
-            ref_array[5].blue()
+            ref_array[5].red(),
+            ref_array[6].red(),
+            ref_array[7].blue(),
+            ref_array[8].bold().blue()
         )
     }
 }
-
-// time,uid,pid,ppid,comm,args
-fn parse_line(line: &str, process_service: &ProcessService) -> anyhow::Result<ProcessExecution> {
-    let parts: Vec<&str> = line.split(",").collect();
-    let timestamp = NaiveDateTime::parse_from_str(parts[0], FORMAT)?;
-    let uid: u32 = parts[1].parse()?;
-    let pid = parts[2].parse()?;
-    let ppid = parts[3].parse()?;
-    let comm = parts[4].trim().to_string();
-    let args = parts[5].trim().to_string();
-
-    let username = process_service
-        .get_user_by_id(uid as usize)
-        .map(|s| s.to_string());
-
-    Ok(ProcessExecution {
-        pid,
-        ppid,
-        comm,
-        args,
-        timestamp,
-        username,
-    })
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parsing_line() {
-        let process_service = ProcessService::new();
-        let line = "2025 Mar 25 21:16:01,1000,12681,3784,systemd,/nix/store/w9qcpyhjrxsqrps91wkz8r4mqvg9zrxc-systemd-256.10/lib/systemd/systemd-executor --deserialize 47 --log-level info --log-target auto";
-        let process = parse_line(line, &process_service).unwrap();
-        assert_eq!(process.pid, 12681);
-        assert_eq!(process.ppid, 3784);
-        assert_eq!(process.comm, "systemd");
-        assert_eq!(
-            process.args,
-            "/nix/store/w9qcpyhjrxsqrps91wkz8r4mqvg9zrxc-systemd-256.10/lib/systemd/systemd-executor --deserialize 47 --log-level info --log-target auto"
-        );
-    }
-}
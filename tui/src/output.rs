@@ -0,0 +1,482 @@
+//! The headless mode, writing one line per event instead of showing the TUI.
+//!
+//! Every line has the same fields, in this order, left out or empty when they do not apply:
+//!
+//! | field               | events                   |                                                  |
+//! |---------------------|--------------------------|--------------------------------------------------|
+//! | `event`             | all                      | `exec`, `exit`, `fork`, `enrich` or `dropped`    |
+//! | `time`              | all                      | RFC 3339 time of the event, `enrich`: its exec   |
+//! | `pid`               | all but `dropped`        |                                                  |
+//! | `ppid`              | `exec`, `fork`           |                                                  |
+//! | `comm`              | `exec`, `fork`           | of the process calling exec or fork              |
+//! | `user`              | `exec`                   |                                                  |
+//! | `gid`               | `exec`                   |                                                  |
+//! | `ret`               | `exec`                   | 0 or a negative errno                            |
+//! | `exit_code`         | `exit`                   |                                                  |
+//! | `signal`            | `exit`                   | the signal that killed the process               |
+//! | `duration_ns`       | `exit`                   | since the last exec of the process               |
+//! | `clone_flags`       | `fork`                   |                                                  |
+//! | `dropped`           | `dropped`                | events lost so far                               |
+//! | `path`              | `exec`                   | absolute path of the executed file               |
+//! | `cwd`               | `enrich`                 | from `/proc` right after the exec                |
+//! | `exe`               | `enrich`                 | from `/proc` right after the exec                |
+//! | `args`              | `exec`                   |                                                  |
+//! | `start_time`        | `exec`, `exit`, `enrich` | clock ticks since boot, as in `/proc/<pid>/stat` |
+//! | `parent_start_time` | `exec`, `fork`           | the `start_time` of the parent                   |
+//!
+//! `enrich` events refer to the exec with the same `pid` and `time`. Together with `pid`, the
+//! `start_time` tells apart processes that got the same pid one after the other, if the backend
+//! knows it. New fields are only ever added at the end.
+
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    sync::mpsc,
+};
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+use crate::{
+    event::{AppEvent, Event},
+    process_tree::ProcessTree,
+};
+
+/// The format of the lines written in headless mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// JSON Lines, one object per line.
+    Json,
+    /// Comma separated values, with a header line.
+    Csv,
+    /// `key=value` pairs separated by spaces.
+    Logfmt,
+}
+
+/// A field of the lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Event,
+    Time,
+    Pid,
+    Ppid,
+    Comm,
+    User,
+    Gid,
+    Ret,
+    ExitCode,
+    Signal,
+    DurationNs,
+    CloneFlags,
+    Dropped,
+    Path,
+    Cwd,
+    Exe,
+    Args,
+    StartTime,
+    ParentStartTime,
+}
+
+impl Field {
+    /// Every field, in the order they are written.
+    const ALL: [Field; 19] = [
+        Field::Event,
+        Field::Time,
+        Field::Pid,
+        Field::Ppid,
+        Field::Comm,
+        Field::User,
+        Field::Gid,
+        Field::Ret,
+        Field::ExitCode,
+        Field::Signal,
+        Field::DurationNs,
+        Field::CloneFlags,
+        Field::Dropped,
+        Field::Path,
+        Field::Cwd,
+        Field::Exe,
+        Field::Args,
+        Field::StartTime,
+        Field::ParentStartTime,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Event => "event",
+            Field::Time => "time",
+            Field::Pid => "pid",
+            Field::Ppid => "ppid",
+            Field::Comm => "comm",
+            Field::User => "user",
+            Field::Gid => "gid",
+            Field::Ret => "ret",
+            Field::ExitCode => "exit_code",
+            Field::Signal => "signal",
+            Field::DurationNs => "duration_ns",
+            Field::CloneFlags => "clone_flags",
+            Field::Dropped => "dropped",
+            Field::Path => "path",
+            Field::Cwd => "cwd",
+            Field::Exe => "exe",
+            Field::Args => "args",
+            Field::StartTime => "start_time",
+            Field::ParentStartTime => "parent_start_time",
+        }
+    }
+}
+
+/// The values of the fields of a line, indexed by [`Field`].
+type Fields = [Option<Value>; Field::ALL.len()];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+enum Value {
+    Str(String),
+    Int(i64),
+    UInt(u64),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Str(s) => f.write_str(s),
+            Value::Int(n) => n.fmt(f),
+            Value::UInt(n) => n.fmt(f),
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::UInt(value.into())
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::UInt(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int(value.into())
+    }
+}
+
+/// The fields that are set as a JSON object, in the order of [`Field::ALL`].
+struct JsonFields<'a>(&'a Fields);
+
+impl Serialize for JsonFields<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (field, value) in Field::ALL.iter().zip(self.0) {
+            if let Some(value) = value {
+                map.serialize_entry(field.name(), value)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// The values of the fields for `event`, `None` for events that are not written.
+fn fields(event: &AppEvent) -> Option<Fields> {
+    const NONE: Option<Value> = None;
+    let mut fields = [NONE; Field::ALL.len()];
+    let mut set = |field: Field, value| fields[field as usize] = value;
+    match event {
+        AppEvent::NewProcess(process) => {
+            set(Field::Event, Some("exec".into()));
+            set(Field::Time, Some(format_time(process.timestamp).into()));
+            set(Field::Pid, Some(process.pid.into()));
+            set(Field::Ppid, Some(process.ppid.into()));
+            set(Field::Comm, Some(process.comm.as_str().into()));
+            set(Field::User, process.username.as_deref().map(Value::from));
+            set(Field::Gid, process.gid.map(Value::from));
+            set(Field::Ret, process.ret.map(Value::from));
+            set(
+                Field::Path,
+                process
+                    .path
+                    .as_ref()
+                    .map(|p| p.display().to_string().into()),
+            );
+            set(Field::Args, Some(process.args.as_str().into()));
+            set(Field::StartTime, process.start_time.map(Value::from));
+            set(
+                Field::ParentStartTime,
+                process.parent_start_time.map(Value::from),
+            );
+        }
+        AppEvent::ProcessExited(exit) => {
+            set(Field::Event, Some("exit".into()));
+            set(Field::Time, Some(format_time(exit.timestamp).into()));
+            set(Field::Pid, Some(exit.pid.into()));
+            set(Field::StartTime, exit.start_time.map(Value::from));
+            set(Field::ExitCode, exit.code.map(Value::from));
+            set(Field::Signal, exit.signal.map(Value::from));
+            set(
+                Field::DurationNs,
+                Some((exit.duration.as_nanos() as u64).into()),
+            );
+        }
+        AppEvent::ProcessForked(fork) => {
+            set(Field::Event, Some("fork".into()));
+            set(Field::Time, Some(format_time(fork.timestamp).into()));
+            set(Field::Pid, Some(fork.pid.into()));
+            set(Field::Ppid, Some(fork.ppid.into()));
+            set(Field::Comm, Some(fork.comm.as_str().into()));
+            set(Field::CloneFlags, Some(fork.clone_flags.into()));
+            set(
+                Field::ParentStartTime,
+                fork.parent_start_time.map(Value::from),
+            );
+        }
+        AppEvent::ProcessEnriched {
+            key,
+            timestamp,
+            snapshot,
+        } => {
+            set(Field::Event, Some("enrich".into()));
+            set(Field::Time, Some(format_time(*timestamp).into()));
+            set(Field::Pid, Some(key.pid.into()));
+            set(Field::StartTime, Some(snapshot.start_time.into()));
+            let path =
+                |p: &Option<std::path::PathBuf>| p.as_ref().map(|p| p.display().to_string().into());
+            set(Field::Cwd, path(&snapshot.cwd));
+            set(Field::Exe, path(&snapshot.exe));
+        }
+        AppEvent::EventsDropped { total, timestamp } => {
+            set(Field::Event, Some("dropped".into()));
+            set(Field::Time, Some(format_time(*timestamp).into()));
+            set(Field::Dropped, Some((*total).into()));
+        }
+        AppEvent::ParentFound { .. }
+        | AppEvent::MalformedOutput { .. }
+        | AppEvent::BackendStarted(_)
+        | AppEvent::BackendExited(_)
+        | AppEvent::BackendFailed { .. }
+        | AppEvent::BackendRestarting { .. }
+        | AppEvent::Print(_)
+        | AppEvent::Quit => return None,
+    }
+    Some(fields)
+}
+
+fn format_time(time: DateTime<Local>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.9f%:z").to_string()
+}
+
+/// Writes events as lines in a [`Format`].
+#[derive(Debug)]
+pub struct OutputWriter<W> {
+    out: W,
+    format: Format,
+    line: Vec<u8>,
+    /// To tell the parents the backend could not, like the table does.
+    process_tree: ProcessTree,
+}
+
+impl<W: Write> OutputWriter<W> {
+    /// Writes the header line to `out`, if the format has one.
+    pub fn new(mut out: W, format: Format) -> io::Result<Self> {
+        if format == Format::Csv {
+            let mut csv = csv::Writer::from_writer(&mut out);
+            csv.write_record(Field::ALL.map(Field::name))?;
+            csv.flush()?;
+        }
+        Ok(Self {
+            out,
+            format,
+            line: Vec::new(),
+            process_tree: ProcessTree::new(),
+        })
+    }
+
+    /// Writes the line of `event`, if it is one that is written.
+    pub fn write(&mut self, event: &AppEvent) -> io::Result<()> {
+        let Some(mut fields) = fields(event) else {
+            return Ok(());
+        };
+        match event {
+            AppEvent::NewProcess(process) => {
+                let parent = match process.ppid {
+                    0 => self
+                        .process_tree
+                        .get(process.key())
+                        .map_or(process.parent_key(), |node| node.parent),
+                    _ => process.parent_key(),
+                };
+                fields[Field::Ppid as usize] = Some(parent.pid.into());
+                fields[Field::ParentStartTime as usize] = parent.start_time.map(Value::from);
+                if !process.failed() {
+                    self.process_tree
+                        .exec(process.key(), parent, process.comm.clone());
+                }
+            }
+            AppEvent::ProcessForked(fork) => {
+                self.process_tree
+                    .fork(fork.key(), fork.parent_key(), fork.comm.clone())
+            }
+            AppEvent::ProcessExited(exit) => self.process_tree.exit(exit.key()),
+            _ => {}
+        }
+        self.line.clear();
+        let line = &mut self.line;
+        match self.format {
+            Format::Json => {
+                serde_json::to_writer(&mut *line, &JsonFields(&fields))?;
+                line.push(b'\n');
+            }
+            Format::Csv => {
+                // Writes the line break itself.
+                let mut csv = csv::Writer::from_writer(&mut *line);
+                csv.write_record(
+                    fields
+                        .iter()
+                        .map(|value| value.as_ref().map(Value::to_string).unwrap_or_default()),
+                )?;
+                csv.flush()?;
+            }
+            Format::Logfmt => {
+                let values = Field::ALL.iter().zip(&fields);
+                let values = values.filter_map(|(field, value)| Some((field, value.as_ref()?)));
+                for (i, (field, value)) in values.enumerate() {
+                    if i > 0 {
+                        line.push(b' ');
+                    }
+                    write!(line, "{}=", field.name())?;
+                    match value {
+                        Value::Str(s) => write_logfmt_str(line, s)?,
+                        value => write!(line, "{value}")?,
+                    }
+                }
+                line.push(b'\n');
+            }
+        }
+        self.out.write_all(line)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Quotes the value as a JSON string if it is empty or has spaces, quotes, equal signs or
+/// control characters.
+fn write_logfmt_str(line: &mut Vec<u8>, s: &str) -> io::Result<()> {
+    if !s.is_empty() && !s.contains(|c: char| c == ' ' || c == '"' || c == '=' || c.is_control()) {
+        line.extend_from_slice(s.as_bytes());
+    } else {
+        serde_json::to_writer(line, s)?;
+    }
+    Ok(())
+}
+
+/// Writes the events received from `events` to `out` until every sender is gone.
+///
+/// Flushes whenever no more events are pending. Stops quietly when the reading side of a pipe
+/// is closed, e.g. by `head`.
+pub fn run(events: mpsc::Receiver<Event>, format: Format, out: impl Write) -> io::Result<()> {
+    match write_events(events, format, out) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+fn write_events(events: mpsc::Receiver<Event>, format: Format, out: impl Write) -> io::Result<()> {
+    let mut writer = OutputWriter::new(out, format)?;
+    while let Ok(event) = events.recv() {
+        for event in std::iter::once(event).chain(events.try_iter()) {
+            let Event::App(event) = event else {
+                continue;
+            };
+            if let Some(notice) = event.notice() {
+                eprintln!("{notice}");
+            }
+            writer.write(&event)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone};
+
+    use super::*;
+    use crate::ProcessExecution;
+
+    /// 2025-03-25T21:16:01.000000005 in the local timezone.
+    fn time() -> DateTime<Local> {
+        let time = NaiveDate::from_ymd_opt(2025, 3, 25)
+            .unwrap()
+            .and_hms_nano_opt(21, 16, 1, 5)
+            .unwrap();
+        Local.from_local_datetime(&time).unwrap()
+    }
+
+    fn lines(format: Format) -> String {
+        let process = ProcessExecution {
+            pid: 42,
+            start_time: Some(987654),
+            ppid: 1,
+            comm: "bash".to_string(),
+            args: "sh -c \"echo a, b\"".to_string(),
+            timestamp: time(),
+            username: Some("me".to_string()),
+            ret: Some(-2),
+            ..Default::default()
+        };
+        let mut writer = OutputWriter::new(Vec::new(), format).unwrap();
+        writer.write(&AppEvent::NewProcess(process)).unwrap();
+        writer
+            .write(&AppEvent::Print("ignored".to_string()))
+            .unwrap();
+        String::from_utf8(writer.out).unwrap()
+    }
+
+    #[test]
+    fn test_formats() {
+        let zone = time().format("%:z");
+        assert_eq!(
+            lines(Format::Json),
+            format!(
+                "{{\"event\":\"exec\",\"time\":\"2025-03-25T21:16:01.000000005{zone}\",\
+                 \"pid\":42,\"ppid\":1,\"comm\":\"bash\",\"user\":\"me\",\"ret\":-2,\
+                 \"args\":\"sh -c \\\"echo a, b\\\"\",\"start_time\":987654}}\n"
+            )
+        );
+        assert_eq!(
+            lines(Format::Csv),
+            format!(
+                "event,time,pid,ppid,comm,user,gid,ret,exit_code,signal,duration_ns,clone_flags,\
+                 dropped,path,cwd,exe,args,start_time,parent_start_time\n\
+                 exec,2025-03-25T21:16:01.000000005{zone},42,1,bash,me,,-2,,,,,,,,,\
+                 \"sh -c \"\"echo a, b\"\"\",987654,\n"
+            )
+        );
+        assert_eq!(
+            lines(Format::Logfmt),
+            format!(
+                "event=exec time=2025-03-25T21:16:01.000000005{zone} pid=42 ppid=1 comm=bash \
+                 user=me ret=-2 args=\"sh -c \\\"echo a, b\\\"\" start_time=987654\n"
+            )
+        );
+    }
+}
@@ -1,47 +1,152 @@
-use std::{ffi::OsStr, path::Path};
+use std::path::{Path, PathBuf};
 
-use sysinfo::{Pid, System, Uid, Users};
+use chrono::{DateTime, Local};
+use sysinfo::{Group, Groups, Pid, ProcessRefreshKind, ProcessesToUpdate, System, Uid, Users};
+
+use crate::{enrich, Lifetime, ProcessExecution, ProcessKey};
 
 #[derive(Debug)]
 pub struct ProcessService {
     system: System,
     users: Users,
+    // `Groups` is not `Debug`.
+    groups: Vec<Group>,
 }
 
-impl ProcessService {
-    pub fn new() -> Self {
+impl Default for ProcessService {
+    fn default() -> Self {
         let mut ret = Self {
             system: System::new_all(),
             users: Users::new(),
+            groups: Vec::new(),
         };
         ret.update();
         ret
     }
+}
+
+impl ProcessService {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
     fn update(&mut self) {
         self.system.refresh_all();
         self.users.refresh();
+        self.groups = Groups::new_with_refreshed_list().into();
+    }
+
+    fn refresh_process(&mut self, pid: Pid) {
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            true,
+            ProcessRefreshKind::everything(),
+        );
     }
 
-    pub fn get_process(&self, pid: usize) -> Option<RenderedProcess> {
-        let p = self.system.process(Pid::from(pid))?;
+    /// The pid of `key` if it is still running, and not some later process with its pid.
+    fn is_running(&mut self, key: ProcessKey) -> Option<Pid> {
+        let pid = Pid::from_u32(key.pid);
+        self.refresh_process(pid);
+        self.system.process(pid)?;
+        let running = ProcessKey::new(key.pid, enrich::read_start_time(key.pid));
+        key.matches(running).then_some(pid)
+    }
 
-        let cwd = p.cwd();
+    /// What `/proc` tells about `key`, `None` once it exited.
+    pub fn get_process(&mut self, key: ProcessKey) -> Option<RenderedProcess> {
+        let pid = self.is_running(key)?;
+        let p = self.system.process(pid)?;
 
-        let user_id = p
+        let user = p
             .user_id()
             .and_then(|u| self.users.get_user_by_id(u))
-            .map(|u| u.name());
-        let env = p
-            .environ()
-            .into_iter()
-            .map(|s| s.to_string_lossy().to_string())
-            .collect();
+            .map(|u| u.name().to_string());
+        let group = p.group_id().and_then(|gid| {
+            self.groups
+                .iter()
+                .find(|g| *g.id() == gid)
+                .map(|g| g.name().to_string())
+        });
+        let disk_usage = p.disk_usage();
 
         Some(RenderedProcess {
-            cwd,
-            user: user_id.unwrap_or(""),
-            env,
+            ppid: p.parent().map(|ppid| ppid.as_u32()),
+            cwd: p.cwd().map(Path::to_path_buf),
+            exe: p.exe().map(Path::to_path_buf),
+            cmd: p
+                .cmd()
+                .iter()
+                .map(|s| s.to_string_lossy().to_string())
+                .collect(),
+            env: p
+                .environ()
+                .iter()
+                .map(|s| s.to_string_lossy().to_string())
+                .collect(),
+            user,
+            group,
+            start_time: start_time(p.start_time()),
+            status: p.status().to_string(),
+            memory: p.memory(),
+            virtual_memory: p.virtual_memory(),
+            disk_read: disk_usage.total_read_bytes,
+            disk_written: disk_usage.total_written_bytes,
+        })
+    }
+
+    /// `key` and the processes above it that are still running, as pid and name.
+    pub fn ancestors(&mut self, key: ProcessKey) -> Vec<(u32, String)> {
+        let mut ancestors = Vec::new();
+        if self.is_running(key).is_none() {
+            return ancestors;
+        }
+        let mut pid = key.pid;
+        // Bounded in case of a pid reused while walking up.
+        while pid != 0 && ancestors.len() < 64 {
+            let sys_pid = Pid::from_u32(pid);
+            self.refresh_process(sys_pid);
+            let Some(p) = self.system.process(sys_pid) else {
+                break;
+            };
+            ancestors.push((pid, p.name().to_string_lossy().to_string()));
+            pid = p.parent().map(|ppid| ppid.as_u32()).unwrap_or_default();
+        }
+        ancestors
+    }
+
+    /// Describes the already running process `key` like an exec of it.
+    ///
+    /// Stands in for the parents of executions when their own exec was not seen.
+    pub fn running_process(&mut self, key: ProcessKey) -> Option<ProcessExecution> {
+        let pid = self.is_running(key)?;
+        let p = self.system.process(pid)?;
+        let ppid = p.parent().map(|ppid| ppid.as_u32()).unwrap_or_default();
+        let args = p
+            .cmd()
+            .iter()
+            .map(|arg| arg.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let timestamp = start_time(p.start_time())?;
+        Some(ProcessExecution {
+            pid: pid.as_u32(),
+            start_time: enrich::read_start_time(key.pid).or(key.start_time),
+            ppid,
+            parent_start_time: enrich::read_start_time(ppid),
+            comm: p.name().to_string_lossy().to_string(),
+            args,
+            timestamp,
+            username: p
+                .user_id()
+                .and_then(|u| self.users.get_user_by_id(u))
+                .map(|u| u.name().to_string()),
+            gid: p.group_id().map(|gid| *gid),
+            ret: None,
+            path: p.exe().map(Path::to_path_buf),
+            lifetime: Lifetime::Running,
+            snapshot: None,
+            raw: None,
         })
     }
 
@@ -51,10 +156,30 @@ impl ProcessService {
     }
 }
 
-#[derive(Debug)]
-pub struct RenderedProcess<'a> {
-    cwd: Option<&'a Path>,
-    user: &'a str,
-    env: Vec<String>,
+fn start_time(secs_since_epoch: u64) -> Option<DateTime<Local>> {
+    let start_time = DateTime::from_timestamp(secs_since_epoch as i64, 0)?;
+    Some(start_time.with_timezone(&Local))
 }
 
+/// A running process as shown in the detail pane.
+#[derive(Debug, Clone)]
+pub struct RenderedProcess {
+    /// The current parent, which took over if the original one exited.
+    pub ppid: Option<u32>,
+    pub cwd: Option<PathBuf>,
+    pub exe: Option<PathBuf>,
+    /// The full argv.
+    pub cmd: Vec<String>,
+    pub env: Vec<String>,
+    pub user: Option<String>,
+    pub group: Option<String>,
+    /// Second precision.
+    pub start_time: Option<DateTime<Local>>,
+    pub status: String,
+    /// Resident memory, in bytes.
+    pub memory: u64,
+    pub virtual_memory: u64,
+    /// Bytes read from disk since the process started.
+    pub disk_read: u64,
+    pub disk_written: u64,
+}
@@ -0,0 +1,157 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::ProcessKey;
+
+/// A process in the [`ProcessTree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessNode {
+    pub key: ProcessKey,
+    pub parent: ProcessKey,
+    pub comm: String,
+    /// Children forked since the node was added.
+    pub children: BTreeSet<u32>,
+}
+
+/// Live parent/child relations of the processes seen forking or executing, dropped again when
+/// they exit.
+///
+/// Lookups go by [`ProcessKey`], so a node is never taken for a later process with its pid.
+#[derive(Debug, Default)]
+pub struct ProcessTree {
+    nodes: HashMap<u32, ProcessNode>,
+}
+
+impl ProcessTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a process forked by `parent`, replacing any stale process with the same pid.
+    pub fn fork(&mut self, key: ProcessKey, parent: ProcessKey, comm: String) {
+        if let Some(stale) = self.nodes.remove(&key.pid) {
+            self.unlink(&stale);
+        }
+        if let Some(node) = self.get_mut(parent) {
+            node.children.insert(key.pid);
+        }
+        self.nodes.insert(
+            key.pid,
+            ProcessNode {
+                key,
+                parent,
+                comm,
+                children: BTreeSet::new(),
+            },
+        );
+    }
+
+    /// Records that `key` executed `comm`, adding it if it forked before it could be seen.
+    ///
+    /// Fills in the start times the fork could not tell.
+    pub fn exec(&mut self, key: ProcessKey, parent: ProcessKey, comm: String) {
+        match self.get_mut(key) {
+            Some(node) => {
+                node.comm = comm;
+                node.key.start_time = node.key.start_time.or(key.start_time);
+                if node.parent.pid == parent.pid {
+                    node.parent.start_time = node.parent.start_time.or(parent.start_time);
+                }
+            }
+            None => self.fork(key, parent, comm),
+        }
+    }
+
+    /// Removes an exited process. Its children stay, pointing at a parent that is gone.
+    pub fn exit(&mut self, key: ProcessKey) {
+        if self.get(key).is_none() {
+            return;
+        }
+        if let Some(node) = self.nodes.remove(&key.pid) {
+            self.unlink(&node);
+        }
+    }
+
+    fn unlink(&mut self, node: &ProcessNode) {
+        if let Some(parent) = self.get_mut(node.parent) {
+            parent.children.remove(&node.key.pid);
+        }
+    }
+
+    pub fn get(&self, key: ProcessKey) -> Option<&ProcessNode> {
+        self.nodes
+            .get(&key.pid)
+            .filter(|node| node.key.matches(key))
+    }
+
+    fn get_mut(&mut self, key: ProcessKey) -> Option<&mut ProcessNode> {
+        self.nodes
+            .get_mut(&key.pid)
+            .filter(|node| node.key.matches(key))
+    }
+
+    /// The known ancestors of `key`, starting with its parent.
+    pub fn ancestors(&self, key: ProcessKey) -> impl Iterator<Item = &ProcessNode> {
+        let mut next = self.get(key).map(|node| node.parent);
+        // Bounded in case pid reuse after a missed exit made the chain cyclic.
+        (0..self.nodes.len()).map_while(move |_| {
+            let node = self.get(next?)?;
+            next = Some(node.parent);
+            Some(node)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(pid: u32, start_time: u64) -> ProcessKey {
+        ProcessKey::new(pid, Some(start_time))
+    }
+
+    #[test]
+    fn test_process_tree() {
+        let mut tree = ProcessTree::new();
+        tree.fork(key(10, 5), key(1, 0), "bash".to_string());
+        tree.fork(ProcessKey::new(11, None), key(10, 5), "bash".to_string());
+        tree.exec(key(11, 7), key(10, 5), "make".to_string());
+        tree.fork(ProcessKey::new(12, None), key(11, 7), "make".to_string());
+        tree.exec(key(12, 8), key(11, 7), "cc".to_string());
+
+        assert_eq!(tree.get(key(10, 5)).unwrap().children, BTreeSet::from([11]));
+        assert_eq!(tree.get(key(11, 7)).unwrap().key, key(11, 7));
+        let ancestors = tree
+            .ancestors(key(12, 8))
+            .map(|n| n.comm.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ancestors, ["make", "bash"]);
+
+        // A later process with the pid of 11 is not 11.
+        assert!(tree.get(key(11, 9)).is_none());
+        tree.exit(key(11, 9));
+        assert_eq!(tree.len(), 3);
+
+        tree.exit(key(11, 7));
+        assert!(tree.get(key(10, 5)).unwrap().children.is_empty());
+        // The exit of a child that never executed has the start time its fork did not.
+        tree.fork(ProcessKey::new(13, None), key(10, 5), "bash".to_string());
+        tree.exit(key(13, 10));
+        assert!(tree.get(key(10, 5)).unwrap().children.is_empty());
+        assert_eq!(tree.get(key(12, 8)).unwrap().parent, key(11, 7));
+        assert_eq!(tree.ancestors(key(12, 8)).count(), 0);
+        assert_eq!(tree.len(), 2);
+
+        // Nor does a reused pid inherit the children or ancestors of the one before.
+        tree.exec(key(11, 9), key(1, 0), "sleep".to_string());
+        assert!(tree.get(key(11, 9)).unwrap().children.is_empty());
+        assert_eq!(tree.ancestors(key(12, 8)).count(), 0);
+    }
+}
@@ -0,0 +1,673 @@
+//! The file format written by `sysrat record` and read by `sysrat replay`.
+//!
+//! A recording starts with [`MAGIC`] and the format version as a little endian `u16`, followed by
+//! records until the end of the file. Every record is a kind byte, the length of its payload as a
+//! little endian `u32`, and the payload. The payload starts with the nanoseconds since the
+//! recording started as a `u64`, followed by the fields of the event:
+//!
+//! - integers are little endian, timestamps are nanoseconds since the epoch as an `i64`
+//! - strings and paths are their length as a `u32` followed by their bytes
+//! - options are a `0` or `1` byte, followed by the value if `1`
+//! - lists are their length as a `u32` followed by their items
+//!
+//! Readers skip the records of kinds they do not know, so new kinds can be added without a new
+//! version. Changing the payload of a known kind needs a new version.
+
+use std::{
+    ffi::OsString,
+    io::{self, Read, Write},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use ebpf_common::ExecHeader;
+
+use crate::{
+    enrich::ProcSnapshot,
+    event::{AppEvent, Event},
+    Lifetime, ProcessExecution, ProcessExit, ProcessFork, ProcessKey, RawExec,
+};
+
+/// The first bytes of every recording.
+pub const MAGIC: [u8; 8] = *b"SYSRATRC";
+
+/// The version written, and the only one read.
+pub const VERSION: u16 = 1;
+
+const KIND_EXEC: u8 = 1;
+const KIND_EXIT: u8 = 2;
+const KIND_FORK: u8 = 3;
+const KIND_ENRICHED: u8 = 4;
+const KIND_DROPPED: u8 = 5;
+
+/// What a recording holds: what the backend reported, and what `/proc` showed after execs.
+#[derive(Debug, Clone)]
+pub enum RecordedEvent {
+    Exec(ProcessExecution),
+    Exit(ProcessExit),
+    Fork(ProcessFork),
+    Enriched {
+        key: ProcessKey,
+        timestamp: DateTime<Local>,
+        snapshot: Arc<ProcSnapshot>,
+    },
+    Dropped {
+        total: u64,
+        timestamp: DateTime<Local>,
+    },
+}
+
+impl RecordedEvent {
+    /// The recorded part of `event`, `None` for the state of the backend and the app around it.
+    pub fn from_app_event(event: AppEvent) -> Option<Self> {
+        let event = match event {
+            AppEvent::NewProcess(process) => RecordedEvent::Exec(process),
+            AppEvent::ProcessExited(exit) => RecordedEvent::Exit(exit),
+            AppEvent::ProcessForked(fork) => RecordedEvent::Fork(fork),
+            AppEvent::ProcessEnriched {
+                key,
+                timestamp,
+                snapshot,
+            } => RecordedEvent::Enriched {
+                key,
+                timestamp,
+                snapshot,
+            },
+            AppEvent::EventsDropped { total, timestamp } => {
+                RecordedEvent::Dropped { total, timestamp }
+            }
+            // Only looked up for the table.
+            AppEvent::ParentFound { .. }
+            | AppEvent::MalformedOutput { .. }
+            | AppEvent::BackendStarted(_)
+            | AppEvent::BackendExited(_)
+            | AppEvent::BackendFailed { .. }
+            | AppEvent::BackendRestarting { .. }
+            | AppEvent::Print(_)
+            | AppEvent::Quit => return None,
+        };
+        Some(event)
+    }
+}
+
+/// Writes events to a recording.
+#[derive(Debug)]
+pub struct Recorder<W> {
+    out: W,
+    started: Instant,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Writes the header of the recording to `out`.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            out,
+            started: Instant::now(),
+            buf: Vec::new(),
+        })
+    }
+
+    /// Appends `event`.
+    pub fn write(&mut self, event: &RecordedEvent) -> io::Result<()> {
+        self.write_at(self.started.elapsed(), event)
+    }
+
+    /// Appends `event` as if it happened `at` into the recording.
+    pub fn write_at(&mut self, at: Duration, event: &RecordedEvent) -> io::Result<()> {
+        let mut e = Encoder(std::mem::take(&mut self.buf));
+        e.0.clear();
+        e.u64(at.as_nanos() as u64);
+        let kind = match event {
+            RecordedEvent::Exec(process) => {
+                e.process(process);
+                KIND_EXEC
+            }
+            RecordedEvent::Exit(exit) => {
+                e.exit(exit);
+                KIND_EXIT
+            }
+            RecordedEvent::Fork(fork) => {
+                e.u32(fork.pid);
+                e.u32(fork.ppid);
+                e.option(fork.parent_start_time, Encoder::u64);
+                e.timestamp(fork.timestamp);
+                e.str(&fork.comm);
+                e.u64(fork.clone_flags);
+                KIND_FORK
+            }
+            RecordedEvent::Enriched {
+                key,
+                timestamp,
+                snapshot,
+            } => {
+                e.u32(key.pid);
+                e.option(key.start_time, Encoder::u64);
+                e.timestamp(*timestamp);
+                e.snapshot(snapshot);
+                KIND_ENRICHED
+            }
+            RecordedEvent::Dropped { total, timestamp } => {
+                e.u64(*total);
+                e.timestamp(*timestamp);
+                KIND_DROPPED
+            }
+        };
+        self.buf = e.0;
+        self.out.write_all(&[kind])?;
+        self.out.write_all(&(self.buf.len() as u32).to_le_bytes())?;
+        self.out.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Records the events received from `events` to `out` until every sender is gone.
+///
+/// Flushes whenever no more events are pending, so that little is lost when interrupted. Returns
+/// how many events were recorded.
+pub fn record(events: mpsc::Receiver<Event>, out: impl Write) -> anyhow::Result<u64> {
+    let mut recorder = Recorder::new(out)?;
+    let mut recorded = 0;
+    while let Ok(event) = events.recv() {
+        for event in std::iter::once(event).chain(events.try_iter()) {
+            let Event::App(event) = event else {
+                continue;
+            };
+            if let Some(notice) = event.notice() {
+                eprintln!("{notice}");
+            }
+            if let Some(event) = RecordedEvent::from_app_event(*event) {
+                recorder.write(&event)?;
+                recorded += 1;
+            }
+        }
+        recorder.flush()?;
+    }
+    Ok(recorded)
+}
+
+/// Reads the events of a recording, in the order they were recorded.
+#[derive(Debug)]
+pub struct Recording<R> {
+    input: R,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> Recording<R> {
+    /// Checks the header of the recording read from `input`.
+    pub fn new(mut input: R) -> anyhow::Result<Self> {
+        let mut header = [0; MAGIC.len() + 2];
+        input
+            .read_exact(&mut header)
+            .context("not a sysrat recording")?;
+        if header[..MAGIC.len()] != MAGIC {
+            anyhow::bail!("not a sysrat recording");
+        }
+        let version = u16::from_le_bytes([header[MAGIC.len()], header[MAGIC.len() + 1]]);
+        if version != VERSION {
+            anyhow::bail!("recording has version {version}, only version {VERSION} is supported");
+        }
+        Ok(Self {
+            input,
+            buf: Vec::new(),
+        })
+    }
+
+    /// The next event and when it happened into the recording, `None` at the end.
+    ///
+    /// A record cut short ends the recording too, the recorder may have been killed while
+    /// writing it.
+    pub fn next_event(&mut self) -> anyhow::Result<Option<(Duration, RecordedEvent)>> {
+        loop {
+            let mut header = [0; 5];
+            if !read_all(&mut self.input, &mut header)? {
+                return Ok(None);
+            }
+            let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+            // Only as much is allocated as the input has, whatever the length claims.
+            self.buf.clear();
+            (&mut self.input)
+                .take(len.into())
+                .read_to_end(&mut self.buf)?;
+            if self.buf.len() < len as usize {
+                return Ok(None);
+            }
+            let mut d = Decoder(&self.buf);
+            let at = Duration::from_nanos(d.u64()?);
+            let event = match header[0] {
+                KIND_EXEC => RecordedEvent::Exec(d.process()?),
+                KIND_EXIT => RecordedEvent::Exit(d.exit()?),
+                KIND_FORK => RecordedEvent::Fork(ProcessFork {
+                    pid: d.u32()?,
+                    ppid: d.u32()?,
+                    parent_start_time: d.option(Decoder::u64)?,
+                    timestamp: d.timestamp()?,
+                    comm: d.str()?,
+                    clone_flags: d.u64()?,
+                }),
+                KIND_ENRICHED => RecordedEvent::Enriched {
+                    key: ProcessKey::new(d.u32()?, d.option(Decoder::u64)?),
+                    timestamp: d.timestamp()?,
+                    snapshot: Arc::new(d.snapshot()?),
+                },
+                KIND_DROPPED => RecordedEvent::Dropped {
+                    total: d.u64()?,
+                    timestamp: d.timestamp()?,
+                },
+                _ => continue,
+            };
+            return Ok(Some((at, event)));
+        }
+    }
+}
+
+/// Fills `buf`, returns `false` if the input ended first.
+fn read_all(input: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match input.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    fn path(&mut self, value: &Path) {
+        self.bytes(value.as_os_str().as_bytes());
+    }
+
+    fn option<T>(&mut self, value: Option<T>, encode: impl FnOnce(&mut Self, T)) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                encode(self, value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn strs(&mut self, values: &[String]) {
+        self.u32(values.len() as u32);
+        for value in values {
+            self.str(value);
+        }
+    }
+
+    fn timestamp(&mut self, value: DateTime<Local>) {
+        let nanos = value.timestamp_nanos_opt().unwrap_or_default();
+        self.u64(nanos as u64);
+    }
+
+    fn process(&mut self, process: &ProcessExecution) {
+        self.u32(process.pid);
+        self.option(process.start_time, Self::u64);
+        self.u32(process.ppid);
+        self.option(process.parent_start_time, Self::u64);
+        self.str(&process.comm);
+        self.str(&process.args);
+        self.timestamp(process.timestamp);
+        self.option(process.username.as_deref(), Self::str);
+        self.option(process.gid, Self::u32);
+        self.option(process.ret, Self::i32);
+        self.option(process.path.as_deref(), Self::path);
+        match process.lifetime {
+            Lifetime::Unknown => self.u8(0),
+            Lifetime::Running => self.u8(1),
+            Lifetime::Replaced(duration) => {
+                self.u8(2);
+                self.u64(duration.as_nanos() as u64);
+            }
+            Lifetime::Exited(exit) => {
+                self.u8(3);
+                self.exit(&exit);
+            }
+        }
+        self.option(process.snapshot.as_deref(), Self::snapshot);
+        self.option(process.raw.as_deref(), Self::raw);
+    }
+
+    fn exit(&mut self, exit: &ProcessExit) {
+        self.u32(exit.pid);
+        self.option(exit.start_time, Self::u64);
+        self.timestamp(exit.timestamp);
+        self.option(exit.code, Self::i32);
+        self.option(exit.signal, Self::i32);
+        self.u64(exit.duration.as_nanos() as u64);
+    }
+
+    fn snapshot(&mut self, snapshot: &ProcSnapshot) {
+        self.u64(snapshot.start_time);
+        self.option(snapshot.cwd.as_deref(), Self::path);
+        self.option(snapshot.exe.as_deref(), Self::path);
+        self.strs(&snapshot.environ);
+        self.option(snapshot.cgroup.as_deref(), Self::str);
+        self.option(snapshot.state.as_deref(), Self::str);
+    }
+
+    fn raw(&mut self, raw: &RawExec) {
+        let header = &raw.header;
+        self.u32(header.kind);
+        self.u32(header.pid);
+        self.u64(header.timestamp);
+        self.u64(header.start_time);
+        self.u64(header.parent_start_time);
+        self.u32(header.uid);
+        self.u32(header.gid);
+        self.u32(header.ppid);
+        self.0.extend_from_slice(&header.comm);
+        self.u32(header.filename_len);
+        self.u32(header.args_len);
+        self.u32(header.envs_len);
+        self.u32(header.flags);
+        self.i32(header.ret);
+        self.u32(header.syscall);
+        self.i32(header.dirfd);
+        self.u32(header.at_flags);
+        self.str(&raw.filename);
+        self.strs(&raw.args);
+        self.strs(&raw.envs);
+    }
+}
+
+/// Reads the payload of a record.
+struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let Some((bytes, rest)) = self.0.split_first_chunk() else {
+            anyhow::bail!("record is too short");
+        };
+        self.0 = rest;
+        Ok(*bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn bytes(&mut self) -> anyhow::Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        if len > self.0.len() {
+            anyhow::bail!("record is too short");
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes.to_vec())
+    }
+
+    fn str(&mut self) -> anyhow::Result<String> {
+        Ok(String::from_utf8(self.bytes()?)?)
+    }
+
+    fn path(&mut self) -> anyhow::Result<PathBuf> {
+        Ok(OsString::from_vec(self.bytes()?).into())
+    }
+
+    fn option<T>(
+        &mut self,
+        decode: impl FnOnce(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => decode(self).map(Some),
+            tag => anyhow::bail!("invalid option tag {tag}"),
+        }
+    }
+
+    fn strs(&mut self) -> anyhow::Result<Vec<String>> {
+        let len = self.u32()?;
+        (0..len).map(|_| self.str()).collect()
+    }
+
+    fn timestamp(&mut self) -> anyhow::Result<DateTime<Local>> {
+        Ok(DateTime::from_timestamp_nanos(self.u64()? as i64).with_timezone(&Local))
+    }
+
+    fn process(&mut self) -> anyhow::Result<ProcessExecution> {
+        Ok(ProcessExecution {
+            pid: self.u32()?,
+            start_time: self.option(Self::u64)?,
+            ppid: self.u32()?,
+            parent_start_time: self.option(Self::u64)?,
+            comm: self.str()?,
+            args: self.str()?,
+            timestamp: self.timestamp()?,
+            username: self.option(Self::str)?,
+            gid: self.option(Self::u32)?,
+            ret: self.option(Self::i32)?,
+            path: self.option(Self::path)?,
+            lifetime: match self.u8()? {
+                0 => Lifetime::Unknown,
+                1 => Lifetime::Running,
+                2 => Lifetime::Replaced(Duration::from_nanos(self.u64()?)),
+                3 => Lifetime::Exited(self.exit()?),
+                tag => anyhow::bail!("invalid lifetime {tag}"),
+            },
+            snapshot: self.option(Self::snapshot)?.map(Arc::new),
+            raw: self.option(Self::raw)?.map(Arc::new),
+        })
+    }
+
+    fn exit(&mut self) -> anyhow::Result<ProcessExit> {
+        Ok(ProcessExit {
+            pid: self.u32()?,
+            start_time: self.option(Self::u64)?,
+            timestamp: self.timestamp()?,
+            code: self.option(Self::i32)?,
+            signal: self.option(Self::i32)?,
+            duration: Duration::from_nanos(self.u64()?),
+        })
+    }
+
+    fn snapshot(&mut self) -> anyhow::Result<ProcSnapshot> {
+        Ok(ProcSnapshot {
+            start_time: self.u64()?,
+            cwd: self.option(Self::path)?,
+            exe: self.option(Self::path)?,
+            environ: self.strs()?,
+            cgroup: self.option(Self::str)?,
+            state: self.option(Self::str)?,
+        })
+    }
+
+    fn raw(&mut self) -> anyhow::Result<RawExec> {
+        let header = ExecHeader {
+            kind: self.u32()?,
+            pid: self.u32()?,
+            timestamp: self.u64()?,
+            start_time: self.u64()?,
+            parent_start_time: self.u64()?,
+            uid: self.u32()?,
+            gid: self.u32()?,
+            ppid: self.u32()?,
+            comm: self.take()?,
+            filename_len: self.u32()?,
+            args_len: self.u32()?,
+            envs_len: self.u32()?,
+            flags: self.u32()?,
+            ret: self.i32()?,
+            syscall: self.u32()?,
+            dirfd: self.i32()?,
+            at_flags: self.u32()?,
+        };
+        Ok(RawExec {
+            header,
+            filename: self.str()?,
+            args: self.strs()?,
+            envs: self.strs()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+
+    fn events() -> Vec<RecordedEvent> {
+        let mut comm = [0; 16];
+        comm[..4].copy_from_slice(b"bash");
+        let raw = RawExec {
+            header: ExecHeader {
+                kind: ebpf_common::EVENT_EXEC,
+                pid: 42,
+                timestamp: 123_456_789,
+                start_time: 10_000_000,
+                parent_start_time: 5_000_000,
+                uid: 1000,
+                gid: 100,
+                ppid: 1,
+                comm,
+                filename_len: 8,
+                args_len: 8,
+                envs_len: 7,
+                flags: 0,
+                ret: 0,
+                syscall: ebpf_common::SYSCALL_EXECVE,
+                dirfd: ebpf_common::AT_FDCWD,
+                at_flags: 0,
+            },
+            filename: "/bin/ls".to_string(),
+            args: vec!["ls".to_string(), "-l".to_string()],
+            envs: vec!["HOME=/".to_string()],
+        };
+        let snapshot = Arc::new(ProcSnapshot {
+            start_time: 987,
+            cwd: Some(PathBuf::from("/tmp")),
+            exe: None,
+            environ: vec!["HOME=/".to_string()],
+            cgroup: Some("0::/user.slice".to_string()),
+            state: None,
+        });
+        let exit = ProcessExit {
+            pid: 42,
+            start_time: Some(1),
+            timestamp: Local::now(),
+            code: None,
+            signal: Some(9),
+            duration: Duration::from_millis(5),
+        };
+        vec![
+            RecordedEvent::Exec(ProcessExecution {
+                pid: 42,
+                start_time: Some(1),
+                ppid: 1,
+                comm: "bash".to_string(),
+                args: "ls -l".to_string(),
+                username: Some("me".to_string()),
+                gid: Some(100),
+                path: Some(PathBuf::from("/usr/bin/ls")),
+                lifetime: Lifetime::Running,
+                raw: Some(Arc::new(raw)),
+                ..Default::default()
+            }),
+            RecordedEvent::Enriched {
+                key: ProcessKey::new(42, Some(1)),
+                timestamp: Local::now(),
+                snapshot,
+            },
+            RecordedEvent::Fork(ProcessFork {
+                pid: 43,
+                ppid: 42,
+                parent_start_time: Some(1),
+                timestamp: Local::now(),
+                comm: "ls".to_string(),
+                clone_flags: 17,
+            }),
+            RecordedEvent::Exit(exit),
+            RecordedEvent::Dropped {
+                total: 7,
+                timestamp: Local::now(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let events = events();
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        assert!(RecordedEvent::from_app_event(AppEvent::Quit).is_none());
+        for (i, event) in events.iter().enumerate() {
+            let at = Duration::from_secs(i as u64);
+            recorder.write_at(at, event).unwrap();
+        }
+        let bytes = recorder.out;
+
+        let mut recording = Recording::new(bytes.as_slice()).unwrap();
+        for (i, event) in events.iter().enumerate() {
+            let (at, read) = recording.next_event().unwrap().unwrap();
+            assert_eq!(at, Duration::from_secs(i as u64));
+            assert_eq!(format!("{read:?}"), format!("{event:?}"));
+        }
+        assert!(recording.next_event().unwrap().is_none());
+
+        // Cut short while the last record was written.
+        let mut recording = Recording::new(&bytes[..bytes.len() - 3]).unwrap();
+        for _ in 0..events.len() - 1 {
+            assert!(recording.next_event().unwrap().is_some());
+        }
+        assert!(recording.next_event().unwrap().is_none());
+
+        // A length past the end of the input is not allocated up front.
+        let mut huge = bytes[..MAGIC.len() + 2].to_vec();
+        huge.push(KIND_DROPPED);
+        huge.extend_from_slice(&u32::MAX.to_le_bytes());
+        huge.extend_from_slice(&[0; 8]);
+        let mut recording = Recording::new(huge.as_slice()).unwrap();
+        assert!(recording.next_event().unwrap().is_none());
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = VERSION as u8 + 1;
+        assert!(Recording::new(newer.as_slice()).is_err());
+        assert!(Recording::new(&b"2025 Mar 25 21:16:01,1000"[..]).is_err());
+    }
+}
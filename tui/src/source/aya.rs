@@ -0,0 +1,211 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Local};
+use tokio::{runtime::Runtime, time::Interval};
+use tokio_stream::StreamExt;
+use userland::{
+    DecodedEvent, DecodedExec, DecodedExit, DecodedFork, EventStream, ExecSnoop, Filters,
+    KernelFilter, Options,
+};
+
+use super::{EventSource, FilterControl, KernelFilters, SourceEvent};
+use crate::{
+    enrich::{read_start_time, start_time_from_nanos},
+    process_service::ProcessService,
+    Lifetime, ProcessExecution, ProcessExit, ProcessFork, RawExec,
+};
+
+/// How often the kernel side drop counter is checked, and the boot time calibrated again.
+const DROPPED_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads executions from the eBPF programs loaded in-process.
+pub struct AyaSource {
+    /// Waits on `events` for the reader thread, which has no runtime of its own.
+    runtime: Runtime,
+    events: EventStream,
+    dropped_check: Interval,
+    process_service: ProcessService,
+    /// The wall clock time of the boot in nanoseconds, see [`userland::boot_time_ns`].
+    boot_time_ns: i64,
+    dropped: u64,
+}
+
+impl AyaSource {
+    /// Loads and attaches the eBPF tracer.
+    pub fn new(options: &Options) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()?;
+        let snoop = ExecSnoop::load(options)?;
+        let (events, dropped_check) = {
+            let _runtime = runtime.enter();
+            (
+                snoop.into_stream()?,
+                tokio::time::interval(DROPPED_CHECK_INTERVAL),
+            )
+        };
+        Ok(Self {
+            runtime,
+            events,
+            dropped_check,
+            process_service: ProcessService::new(),
+            boot_time_ns: userland::boot_time_ns()?,
+            dropped: 0,
+        })
+    }
+}
+
+impl EventSource for AyaSource {
+    fn filter_control(&mut self) -> Option<Box<dyn FilterControl>> {
+        let filter = self.events.get_mut().take_filter()?;
+        Some(Box::new(AyaFilterControl(filter)))
+    }
+
+    fn next_event(&mut self) -> anyhow::Result<Option<SourceEvent>> {
+        loop {
+            let Self {
+                runtime,
+                events,
+                dropped_check,
+                ..
+            } = self;
+            // `None` when it is time to check the drop counter.
+            let event = runtime.block_on(async {
+                tokio::select! {
+                    event = events.next() => Some(event),
+                    _ = dropped_check.tick() => None,
+                }
+            });
+            let event = match event {
+                Some(Some(event)) => event?,
+                // The stream never ends on its own.
+                Some(None) => return Ok(None),
+                None => {
+                    // Follows the wall clock when it is set.
+                    self.boot_time_ns = userland::boot_time_ns()?;
+                    let dropped = self.events.get_ref().dropped_events()?;
+                    if dropped > self.dropped {
+                        self.dropped = dropped;
+                        return Ok(Some(SourceEvent::Dropped {
+                            total: dropped,
+                            timestamp: Local::now(),
+                        }));
+                    }
+                    continue;
+                }
+            };
+            return Ok(Some(match event {
+                DecodedEvent::Exec(event) => SourceEvent::Exec(to_process_execution(
+                    &event,
+                    self.boot_time_ns,
+                    &self.process_service,
+                )),
+                DecodedEvent::Exit(event) => SourceEvent::Exit(to_exit(&event, self.boot_time_ns)),
+                DecodedEvent::Fork(event) => SourceEvent::Fork(to_fork(&event, self.boot_time_ns)),
+            }));
+        }
+    }
+}
+
+/// Updates the filter maps of the eBPF programs.
+struct AyaFilterControl(KernelFilter);
+
+impl FilterControl for AyaFilterControl {
+    fn apply(&mut self, filters: &KernelFilters) -> anyhow::Result<()> {
+        self.0.apply(&to_filters(filters))
+    }
+}
+
+impl std::fmt::Debug for AyaFilterControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AyaFilterControl").finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn to_filters(filters: &KernelFilters) -> Filters {
+    Filters {
+        uids: filters.uids.clone(),
+        exclude_uids: filters.exclude_uids.clone(),
+        comms: filters.comms.clone(),
+        exclude_comms: filters.exclude_comms.clone(),
+        pid: filters.pid,
+    }
+}
+
+fn to_process_execution(
+    event: &DecodedExec,
+    boot_time_ns: i64,
+    process_service: &ProcessService,
+) -> ProcessExecution {
+    let mut args = event.args.join(" ");
+    if event.args_truncated() {
+        args.push_str(" ...");
+    }
+
+    let username = process_service
+        .get_user_by_id(event.header.uid as usize)
+        .map(|s| s.to_string());
+
+    ProcessExecution {
+        pid: event.header.pid,
+        start_time: start_time(event.header.start_time, event.header.pid),
+        ppid: event.header.ppid,
+        parent_start_time: start_time_from_nanos(event.header.parent_start_time),
+        comm: event.comm.clone(),
+        args,
+        timestamp: wall_clock(boot_time_ns, event.header.timestamp),
+        username,
+        gid: Some(event.header.gid),
+        ret: Some(event.header.ret),
+        path: userland::resolve_path(event),
+        lifetime: if event.header.ret == 0 {
+            Lifetime::Running
+        } else {
+            Lifetime::Unknown
+        },
+        snapshot: None,
+        raw: Some(Arc::new(RawExec {
+            header: event.header,
+            filename: event.filename.clone(),
+            args: event.args.clone(),
+            envs: event.envs.clone(),
+        })),
+    }
+}
+
+/// The start time of `pid` as the kernel reported it, or else as `/proc` shows it by now.
+///
+/// The kernel reports none when the `task_struct` layout the eBPF programs were built against
+/// does not match the running kernel. Parents are left without one instead, their pid may well
+/// belong to another process by the time the event is read.
+fn start_time(nanos: u64, pid: u32) -> Option<u64> {
+    start_time_from_nanos(nanos).or_else(|| read_start_time(pid))
+}
+
+/// The wall clock time of a `CLOCK_BOOTTIME` `timestamp` of the eBPF programs.
+fn wall_clock(boot_time_ns: i64, timestamp: u64) -> DateTime<Local> {
+    DateTime::from_timestamp_nanos(boot_time_ns + timestamp as i64).with_timezone(&Local)
+}
+
+fn to_exit(event: &DecodedExit, boot_time_ns: i64) -> ProcessExit {
+    ProcessExit {
+        pid: event.header.pid,
+        start_time: start_time(event.header.start_time, event.header.pid),
+        timestamp: wall_clock(boot_time_ns, event.header.timestamp),
+        code: event.exit_code(),
+        signal: event.signal(),
+        duration: event.duration(),
+    }
+}
+
+fn to_fork(event: &DecodedFork, boot_time_ns: i64) -> ProcessFork {
+    ProcessFork {
+        pid: event.header.pid,
+        ppid: event.header.ppid,
+        parent_start_time: start_time_from_nanos(event.header.parent_start_time),
+        timestamp: wall_clock(boot_time_ns, event.header.timestamp),
+        comm: event.comm.clone(),
+        clone_flags: event.header.clone_flags,
+    }
+}
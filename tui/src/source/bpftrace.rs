@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use duct::cmd;
+use serde::Deserialize;
+
+use super::{child::ChildOutput, EventSource, SourceEvent};
+use crate::{
+    enrich::start_time_from_nanos, process_service::ProcessService, Lifetime, ProcessExecution,
+};
+
+/// Timestamp format printed by `execsnoop.bpf`, local time with microseconds.
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// Path of the bpftrace script, relative to the working directory.
+const SCRIPT: &str = "execsnoop.bpf";
+
+/// Reads executions from a `bpftrace` child process running `execsnoop.bpf`.
+pub struct BpftraceSource {
+    lines: ChildOutput,
+    parser: Parser,
+    process_service: ProcessService,
+}
+
+impl BpftraceSource {
+    /// Spawns `bpftrace`.
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            lines: ChildOutput::spawn("bpftrace", cmd!("bpftrace", "-f", "json", "-q", SCRIPT))?,
+            parser: Parser::default(),
+            process_service: ProcessService::new(),
+        })
+    }
+}
+
+impl EventSource for BpftraceSource {
+    fn next_event(&mut self) -> anyhow::Result<Option<SourceEvent>> {
+        next_event(&mut self.lines, &mut self.parser, &self.process_service)
+    }
+}
+
+/// Parses `lines` of `bpftrace -f json execsnoop.bpf` output until they yield an event.
+pub(crate) fn next_event<E: Into<anyhow::Error>>(
+    lines: &mut impl Iterator<Item = Result<String, E>>,
+    parser: &mut Parser,
+    process_service: &ProcessService,
+) -> anyhow::Result<Option<SourceEvent>> {
+    for line in lines {
+        if let Some(event) = parser.parse(&line.map_err(Into::into)?, process_service) {
+            return Ok(Some(event));
+        }
+    }
+    Ok(None)
+}
+
+/// A line of `bpftrace -f json` output.
+#[derive(Debug, Deserialize)]
+struct Message {
+    #[serde(rename = "type")]
+    kind: String,
+    data: serde_json::Value,
+}
+
+/// Turns the messages of `execsnoop.bpf` into events.
+///
+/// Every exec is printed as a message per arg and then one with the fields, all of them carry the
+/// tid of the exec as the messages of different CPUs may interleave.
+#[derive(Debug, Default)]
+pub(crate) struct Parser {
+    /// The args printed so far, by tid, until their exec.
+    args: HashMap<u32, Vec<String>>,
+    malformed: u64,
+    lost: u64,
+}
+
+impl Parser {
+    /// Parses one line, returns the event it completes, if any.
+    ///
+    /// Lines that cannot be parsed are reported as [`SourceEvent::Malformed`].
+    pub fn parse(&mut self, line: &str, process_service: &ProcessService) -> Option<SourceEvent> {
+        match self.parse_message(line, process_service) {
+            Ok(event) => event,
+            Err(e) => {
+                self.malformed += 1;
+                Some(SourceEvent::Malformed {
+                    total: self.malformed,
+                    error: format!("{e:#}"),
+                })
+            }
+        }
+    }
+
+    fn parse_message(
+        &mut self,
+        line: &str,
+        process_service: &ProcessService,
+    ) -> anyhow::Result<Option<SourceEvent>> {
+        let message: Message = serde_json::from_str(line)
+            .with_context(|| format!("not a bpftrace message: {line}"))?;
+        let data = || {
+            message
+                .data
+                .as_str()
+                .with_context(|| format!("expected a string in {line}"))
+        };
+        match message.kind.as_str() {
+            "printf" => match data()?.split_once(',') {
+                Some(("arg", arg)) => {
+                    let (tid, index, arg) = parse_arg(arg)?;
+                    let args = self.args.entry(tid).or_default();
+                    // The exec of earlier args got lost.
+                    if index == 0 {
+                        args.clear();
+                    }
+                    args.push(arg.to_string());
+                    Ok(None)
+                }
+                Some(("exec", fields)) => {
+                    let (tid, mut process) = parse_fields(fields, process_service)?;
+                    process.args = self.args.remove(&tid).unwrap_or_default().join(" ");
+                    Ok(Some(SourceEvent::Exec(process)))
+                }
+                _ => anyhow::bail!("unexpected message: {line}"),
+            },
+            "lost_events" => {
+                let lost = message.data["events"]
+                    .as_u64()
+                    .with_context(|| format!("expected a number of events in {line}"))?;
+                self.lost += lost;
+                Ok(Some(SourceEvent::Dropped {
+                    total: self.lost,
+                    timestamp: Local::now(),
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// tid,index,arg
+fn parse_arg(fields: &str) -> anyhow::Result<(u32, usize, &str)> {
+    let parts: Vec<&str> = fields.splitn(3, ',').collect();
+    let [tid, index, arg] = parts[..] else {
+        anyhow::bail!("expected 3 fields, got {}: {fields}", parts.len());
+    };
+    let context = || format!("invalid arg: {fields}");
+    Ok((
+        tid.parse().with_context(context)?,
+        index.parse().with_context(context)?,
+        arg,
+    ))
+}
+
+// time,uid,pid,tid,ppid,start_time,parent_start_time,comm
+fn parse_fields(
+    fields: &str,
+    process_service: &ProcessService,
+) -> anyhow::Result<(u32, ProcessExecution)> {
+    let parts: Vec<&str> = fields.trim_end_matches('\n').splitn(8, ',').collect();
+    let [time, uid, pid, tid, ppid, start_time, parent_start_time, comm] = parts[..] else {
+        anyhow::bail!("expected 8 fields, got {}: {fields}", parts.len());
+    };
+    let context = || format!("invalid fields: {fields}");
+    let timestamp = NaiveDateTime::parse_from_str(time, TIME_FORMAT).with_context(context)?;
+    // An hour repeated when the clocks go back is ambiguous, the first one is as good a guess.
+    let timestamp = Local
+        .from_local_datetime(&timestamp)
+        .earliest()
+        .with_context(context)?;
+    let uid: u32 = uid.parse().with_context(context)?;
+
+    let username = process_service
+        .get_user_by_id(uid as usize)
+        .map(|s| s.to_string());
+
+    let tid = tid.parse().with_context(context)?;
+    Ok((
+        tid,
+        ProcessExecution {
+            pid: pid.parse().with_context(context)?,
+            start_time: start_time_from_nanos(start_time.parse().with_context(context)?),
+            ppid: ppid.parse().with_context(context)?,
+            parent_start_time: start_time_from_nanos(
+                parent_start_time.parse().with_context(context)?,
+            ),
+            comm: comm.to_string(),
+            args: String::new(),
+            timestamp,
+            username,
+            gid: None,
+            ret: None,
+            path: None,
+            lifetime: Lifetime::Unknown,
+            snapshot: None,
+            raw: None,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(lines: &[&str]) -> Vec<SourceEvent> {
+        let process_service = ProcessService::new();
+        let mut parser = Parser::default();
+        let mut lines = lines
+            .iter()
+            .map(|line| Ok::<_, std::io::Error>(line.to_string()));
+        std::iter::from_fn(|| next_event(&mut lines, &mut parser, &process_service).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parsing_messages() {
+        let events = parse_all(&[
+            r#"{"type": "attached_probes", "data": {"probes": 2}}"#,
+            r#"{"type": "printf", "data": "arg,12681,0,sh"}"#,
+            r#"{"type": "printf", "data": "arg,12681,1,-c"}"#,
+            // Another CPU.
+            r#"{"type": "printf", "data": "arg,7,0,ls"}"#,
+            r#"{"type": "printf", "data": "arg,12681,2,echo a,b\nc"}"#,
+            r#"{"type": "printf", "data": "exec,2025-03-25T21:16:01.123456,1000,12681,12681,3784,7000000000,20000000,sys,temd"}"#,
+            r#"{"type": "lost_events", "data": {"events": 3}}"#,
+            r#"{"type": "printf", "data": "exec,2025-03-25T21:16:02.5,0,1,7,0,0,0,init"}"#,
+        ]);
+        let [SourceEvent::Exec(process), SourceEvent::Dropped { total: 3, .. }, SourceEvent::Exec(last)] =
+            &events[..]
+        else {
+            panic!("unexpected events {events:?}");
+        };
+        assert_eq!(process.pid, 12681);
+        assert_eq!(process.ppid, 3784);
+        assert!(process.start_time.is_some_and(|t| t > 0));
+        assert!(process.parent_start_time < process.start_time);
+        assert_eq!(process.comm, "sys,temd");
+        assert_eq!(process.args, "sh -c echo a,b\nc");
+        assert_eq!(process.timestamp.timestamp_subsec_micros(), 123456);
+        assert_eq!((last.pid, last.args.as_str()), (1, "ls"));
+        assert_eq!(last.start_time, None);
+    }
+
+    #[test]
+    fn test_parsing_malformed_lines() {
+        let events = parse_all(&[
+            "2025 Mar 25 21:16:01,1000,12681,3784,systemd,/bin/ls",
+            r#"{"type": "printf", "data": "exec,2025-03-25T21:16:01,1000,12681"}"#,
+            r#"{"type": "printf", "data": "arg,ls"}"#,
+        ]);
+        let totals = events
+            .iter()
+            .map(|event| match event {
+                SourceEvent::Malformed { total, .. } => *total,
+                _ => panic!("unexpected event {event:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(totals, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{BufRead, BufReader};
+
+use duct::{Expression, ReaderHandle};
+
+/// How many lines of stderr are kept in the error of a failed backend.
+const STDERR_TAIL: usize = 5;
+
+/// The stdout of a backend running as a child process, line by line.
+///
+/// Its stderr is captured rather than written over the TUI, and ends up in the error once the
+/// process exits unsuccessfully.
+pub struct ChildOutput {
+    name: &'static str,
+    reader: BufReader<ReaderHandle>,
+    exited: bool,
+}
+
+impl ChildOutput {
+    pub fn spawn(name: &'static str, expression: Expression) -> anyhow::Result<Self> {
+        let reader = expression
+            .stderr_capture()
+            .unchecked()
+            .reader()
+            .map_err(|e| anyhow::anyhow!("failed to start {name}: {e}"))?;
+        Ok(Self {
+            name,
+            reader: BufReader::new(reader),
+            exited: false,
+        })
+    }
+
+    /// Fails with the exit status and the end of stderr, unless the process succeeded.
+    fn check_exit(&self) -> anyhow::Result<()> {
+        let Some(output) = self.reader.get_ref().try_wait()? else {
+            return Ok(());
+        };
+        if output.status.success() {
+            return Ok(());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines = stderr.lines().filter(|line| !line.trim().is_empty());
+        let tail = lines.rev().take(STDERR_TAIL).collect::<Vec<_>>();
+        let mut error = format!("{} {}", self.name, output.status);
+        for line in tail.into_iter().rev() {
+            error.push_str("\n  ");
+            error.push_str(line.trim_end());
+        }
+        Err(anyhow::anyhow!(error))
+    }
+}
+
+impl Iterator for ChildOutput {
+    type Item = anyhow::Result<String>;
+
+    /// The next line, or the failure of the process once its output ended.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.exited {
+            return None;
+        }
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => {
+                self.exited = true;
+                self.check_exit().err().map(Err)
+            }
+            Ok(_) => {
+                let len = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(len);
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use duct::cmd;
+
+    use super::*;
+
+    #[test]
+    fn test_child_output() {
+        let output = ChildOutput::spawn("sh", cmd!("sh", "-c", "echo a; echo b")).unwrap();
+        let lines = output.collect::<anyhow::Result<Vec<_>>>().unwrap();
+        assert_eq!(lines, ["a", "b"]);
+
+        let script = "echo a; echo one >&2; echo two >&2; exit 3";
+        let mut output = ChildOutput::spawn("sh", cmd!("sh", "-c", script)).unwrap();
+        assert_eq!(output.next().unwrap().unwrap(), "a");
+        let error = output.next().unwrap().unwrap_err().to_string();
+        assert_eq!(error, "sh exit status: 3\n  one\n  two");
+        assert!(output.next().is_none());
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Local};
+use duct::cmd;
+
+use super::{child::ChildOutput, EventSource, SourceEvent};
+use crate::{Lifetime, ProcessExecution};
+
+/// Path of the dtrace script, relative to the working directory.
+const SCRIPT: &str = "./execsnoop.d";
+
+/// Reads executions from a `dtrace` child process running `execsnoop.d`.
+pub struct DtraceSource {
+    lines: ChildOutput,
+    malformed: u64,
+}
+
+impl DtraceSource {
+    /// Spawns `dtrace`.
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            lines: ChildOutput::spawn("dtrace", cmd!("dtrace", "-s", SCRIPT))?,
+            malformed: 0,
+        })
+    }
+}
+
+impl EventSource for DtraceSource {
+    fn next_event(&mut self) -> anyhow::Result<Option<SourceEvent>> {
+        let Some(line) = self.lines.next() else {
+            return Ok(None);
+        };
+        let line = line?;
+        Ok(Some(match parse_line(&line) {
+            Ok(process) => SourceEvent::Exec(process),
+            Err(e) => {
+                self.malformed += 1;
+                SourceEvent::Malformed {
+                    total: self.malformed,
+                    error: format!("{e:#}: {line}"),
+                }
+            }
+        }))
+    }
+}
+
+// time in nanoseconds since the epoch,pid,ppid,args
+//
+// `execsnoop.d` has no access to the uid or comm, so the comm is derived from argv[0].
+fn parse_line(line: &str) -> anyhow::Result<ProcessExecution> {
+    let parts: Vec<&str> = line.splitn(4, ',').collect();
+    if parts.len() < 4 {
+        anyhow::bail!("expected 4 fields, got {}", parts.len());
+    }
+    let timestamp = DateTime::from_timestamp_nanos(parts[0].trim().parse()?).with_timezone(&Local);
+    let pid = parts[1].trim().parse()?;
+    let ppid = parts[2].trim().parse()?;
+    let args = parts[3].trim().to_string();
+    let comm = args
+        .split_whitespace()
+        .next()
+        .and_then(|argv0| argv0.rsplit('/').next())
+        .unwrap_or_default()
+        .to_string();
+
+    // `execsnoop.d` has no start times either, the rows are told apart by pid only.
+    Ok(ProcessExecution {
+        pid,
+        start_time: None,
+        ppid,
+        parent_start_time: None,
+        comm,
+        args,
+        timestamp,
+        username: None,
+        gid: None,
+        ret: None,
+        path: None,
+        lifetime: Lifetime::Unknown,
+        snapshot: None,
+        raw: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_line() {
+        let process = parse_line("1742937361123456789,4242,1,/bin/ls -la /tmp").unwrap();
+        assert_eq!(process.pid, 4242);
+        assert_eq!(process.ppid, 1);
+        assert_eq!(process.comm, "ls");
+        assert_eq!(process.args, "/bin/ls -la /tmp");
+        assert_eq!(process.timestamp.timestamp_subsec_nanos(), 123456789);
+    }
+}
@@ -0,0 +1,330 @@
+//! Event sources feeding process executions into the [`App`](crate::app::App).
+//!
+//! Every backend implements [`EventSource`]; [`spawn`] drives one of them on a dedicated reader
+//! thread and forwards what it yields as [`AppEvent`]s.
+
+use std::{
+    fmt::{self, Display},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+
+use crate::{
+    cli::Cli,
+    enrich::{Enricher, ProcSnapshot},
+    event::{AppEvent, Event},
+    ProcessExecution, ProcessExit, ProcessFork, ProcessKey,
+};
+
+#[cfg(target_os = "linux")]
+pub mod aya;
+pub mod bpftrace;
+mod child;
+pub mod dtrace;
+pub mod recording;
+pub mod replay;
+
+/// Something an [`EventSource`] observed.
+#[derive(Debug, Clone)]
+pub enum SourceEvent {
+    /// A process was executed.
+    Exec(ProcessExecution),
+    /// A process seen forking or executing exited.
+    Exit(ProcessExit),
+    /// A process was forked.
+    Fork(ProcessFork),
+    /// What `/proc` showed after an exec, only replayed from recordings.
+    Enriched {
+        key: ProcessKey,
+        timestamp: DateTime<Local>,
+        snapshot: Arc<ProcSnapshot>,
+    },
+    /// The backend lost events it could not deliver in time.
+    ///
+    /// Carries the total number of events lost since the source was opened, and when that was
+    /// noticed.
+    Dropped {
+        total: u64,
+        timestamp: DateTime<Local>,
+    },
+    /// The backend printed something that could not be parsed.
+    Malformed {
+        /// Lines that could not be parsed since the source was opened.
+        total: u64,
+        error: String,
+    },
+}
+
+/// A producer of [`SourceEvent`] values.
+pub trait EventSource: Send {
+    /// Blocks until the next event is available.
+    ///
+    /// Returns `Ok(None)` once the source is exhausted and will not produce anything else.
+    fn next_event(&mut self) -> anyhow::Result<Option<SourceEvent>>;
+
+    /// Hands out the control over the [`KernelFilters`] of the source, if it supports them.
+    ///
+    /// Called once before the source is moved to the reader thread.
+    fn filter_control(&mut self) -> Option<Box<dyn FilterControl>> {
+        None
+    }
+}
+
+/// Filters applied before events even reach sysrat. Everything passes when empty.
+///
+/// The comm is the one of the process calling exec, as shown in the comm column.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelFilters {
+    pub uids: Vec<u32>,
+    pub exclude_uids: Vec<u32>,
+    pub comms: Vec<String>,
+    pub exclude_comms: Vec<String>,
+    /// Only this process and the processes it forks.
+    pub pid: Option<u32>,
+}
+
+impl KernelFilters {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for KernelFilters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        parts.extend(self.uids.iter().map(|uid| format!("uid={uid}")));
+        parts.extend(self.exclude_uids.iter().map(|uid| format!("uid!={uid}")));
+        parts.extend(self.comms.iter().map(|comm| format!("comm={comm}")));
+        parts.extend(
+            self.exclude_comms
+                .iter()
+                .map(|comm| format!("comm!={comm}")),
+        );
+        parts.extend(self.pid.map(|pid| format!("pid={pid}")));
+        f.write_str(&parts.join(" "))
+    }
+}
+
+/// Changes the [`KernelFilters`] of a running [`EventSource`] from another thread.
+pub trait FilterControl: Send + fmt::Debug {
+    /// Replaces the filters in effect.
+    fn apply(&mut self, filters: &KernelFilters) -> anyhow::Result<()>;
+}
+
+/// A [`FilterControl`] that outlives the source it controls.
+///
+/// When the source is reopened, the control of the new one takes over, and the filters last
+/// applied are applied to it as well.
+#[derive(Debug, Clone)]
+pub struct SharedFilterControl(Arc<Mutex<SharedFilters>>);
+
+#[derive(Debug)]
+struct SharedFilters {
+    control: Box<dyn FilterControl>,
+    filters: KernelFilters,
+}
+
+impl SharedFilterControl {
+    /// Shares `control`, whose source currently applies `filters`.
+    pub fn new(control: Box<dyn FilterControl>, filters: KernelFilters) -> Self {
+        Self(Arc::new(Mutex::new(SharedFilters { control, filters })))
+    }
+
+    /// Hands over to the control of a reopened source.
+    pub fn replace(&self, control: Box<dyn FilterControl>) -> anyhow::Result<()> {
+        let mut shared = self.0.lock().unwrap();
+        shared.control = control;
+        let filters = shared.filters.clone();
+        shared.control.apply(&filters)
+    }
+}
+
+impl FilterControl for SharedFilterControl {
+    fn apply(&mut self, filters: &KernelFilters) -> anyhow::Result<()> {
+        let mut shared = self.0.lock().unwrap();
+        shared.control.apply(filters)?;
+        shared.filters = filters.clone();
+        Ok(())
+    }
+}
+
+/// The available [`EventSource`] implementations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Backend {
+    /// Load the eBPF programs in-process (Linux only).
+    #[cfg_attr(target_os = "linux", default)]
+    Aya,
+    /// Spawn `bpftrace` with the `execsnoop.bpf` script.
+    Bpftrace,
+    /// Spawn `dtrace` with the `execsnoop.d` script.
+    #[cfg_attr(not(target_os = "linux"), default)]
+    Dtrace,
+    /// Read previously captured `bpftrace` output from a file.
+    Replay,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Backend::Aya => "aya",
+            Backend::Bpftrace => "bpftrace",
+            Backend::Dtrace => "dtrace",
+            Backend::Replay => "replay",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Creates the [`EventSource`] selected on the command line.
+pub fn open(cli: &Cli) -> anyhow::Result<Box<dyn EventSource>> {
+    let filters = cli.kernel_filters();
+    if cli.backend != Backend::Aya && !filters.is_empty() {
+        anyhow::bail!("filtering by uid, comm or pid needs the aya backend");
+    }
+    Ok(match cli.backend {
+        #[cfg(target_os = "linux")]
+        Backend::Aya => Box::new(aya::AyaSource::new(&userland::Options {
+            ring_buf_size: cli.ring_buffer_size,
+            filters: aya::to_filters(&filters),
+        })?),
+        #[cfg(not(target_os = "linux"))]
+        Backend::Aya => anyhow::bail!("the aya backend is only available on Linux"),
+        Backend::Bpftrace => Box::new(bpftrace::BpftraceSource::new()?),
+        Backend::Dtrace => Box::new(dtrace::DtraceSource::new()?),
+        Backend::Replay => {
+            let path = cli
+                .replay_file
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("the replay backend requires --replay-file"))?;
+            Box::new(replay::ReplaySource::new(path)?)
+        }
+    })
+}
+
+/// Opens the backend again after it failed or exited, see [`spawn`].
+pub type Reopen = Box<dyn FnMut() -> anyhow::Result<Box<dyn EventSource>> + Send>;
+
+/// The delay before the first restart of a backend, doubled with every failure in a row.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest delay between restarts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long a backend has to run for its failures no longer to count as in a row.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Why [`forward`] stopped.
+enum Stop {
+    Exhausted,
+    Failed(anyhow::Error),
+    /// The receiving side went away.
+    Disconnected,
+}
+
+/// Spawns the reader thread that drains `source` into `sender`.
+///
+/// Executions are also handed to `enricher`, if any. The changes of the backend are reported as
+/// [`AppEvent::BackendStarted`] and the like. When the source is exhausted or fails, it is opened
+/// again with `reopen` after a growing delay, if given. The thread ends once that is not possible
+/// or the receiving side goes away.
+pub fn spawn(
+    backend: Backend,
+    mut source: Box<dyn EventSource>,
+    sender: mpsc::Sender<Event>,
+    enricher: Option<Enricher>,
+    mut reopen: Option<Reopen>,
+) -> JoinHandle<()> {
+    let send = move |event| sender.send(Event::App(Box::new(event))).is_ok();
+    thread::spawn(move || {
+        let mut failures = 0;
+        loop {
+            if !send(AppEvent::BackendStarted(backend)) {
+                return;
+            }
+            let started = Instant::now();
+            let stop = forward(&mut *source, enricher.as_ref(), &send);
+            if started.elapsed() >= STABLE_AFTER {
+                failures = 0;
+            }
+            let sent = match stop {
+                Stop::Exhausted => send(AppEvent::BackendExited(backend)),
+                Stop::Failed(e) => send(AppEvent::BackendFailed {
+                    backend,
+                    error: format!("{e:#}"),
+                }),
+                Stop::Disconnected => return,
+            };
+            let Some(reopen) = reopen.as_mut().filter(|_| sent) else {
+                return;
+            };
+            source = loop {
+                failures += 1;
+                let delay = INITIAL_BACKOFF
+                    .saturating_mul(1 << (failures - 1).min(16))
+                    .min(MAX_BACKOFF);
+                if !send(AppEvent::BackendRestarting {
+                    backend,
+                    attempt: failures,
+                    delay,
+                }) {
+                    return;
+                }
+                thread::sleep(delay);
+                match reopen() {
+                    Ok(source) => break source,
+                    Err(e) => {
+                        let error = format!("{e:#}");
+                        if !send(AppEvent::BackendFailed { backend, error }) {
+                            return;
+                        }
+                    }
+                }
+            };
+        }
+    })
+}
+
+/// Sends the events of `source` until it stops.
+fn forward(
+    source: &mut dyn EventSource,
+    enricher: Option<&Enricher>,
+    send: &impl Fn(AppEvent) -> bool,
+) -> Stop {
+    loop {
+        let event = match source.next_event() {
+            Ok(Some(event)) => event,
+            Ok(None) => return Stop::Exhausted,
+            Err(e) => return Stop::Failed(e),
+        };
+        let app_event = match event {
+            SourceEvent::Exec(process) => {
+                if let Some(enricher) = enricher {
+                    enricher.enrich(&process);
+                }
+                AppEvent::NewProcess(process)
+            }
+            SourceEvent::Exit(exit) => AppEvent::ProcessExited(exit),
+            SourceEvent::Fork(fork) => AppEvent::ProcessForked(fork),
+            SourceEvent::Enriched {
+                key,
+                timestamp,
+                snapshot,
+            } => AppEvent::ProcessEnriched {
+                key,
+                timestamp,
+                snapshot,
+            },
+            SourceEvent::Dropped { total, timestamp } => {
+                AppEvent::EventsDropped { total, timestamp }
+            }
+            SourceEvent::Malformed { total, error } => AppEvent::MalformedOutput { total, error },
+        };
+        if !send(app_event) {
+            return Stop::Disconnected;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fmt::{self, Display},
+    fs::File,
+    io::BufReader,
+    path::Path,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+use super::{EventSource, SourceEvent};
+use crate::recording::{RecordedEvent, Recording};
+
+/// How fast a recording is replayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    /// This many times faster than recorded.
+    Factor(f64),
+    /// Without waiting between events.
+    Max,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Self::Factor(1.0)
+    }
+}
+
+impl FromStr for Speed {
+    type Err = anyhow::Error;
+
+    /// Parses `max` or a factor like `10` or `10x`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if s == "max" {
+            return Ok(Self::Max);
+        }
+        let factor: f64 = s
+            .strip_suffix('x')
+            .unwrap_or(s)
+            .parse()
+            .context("expected a factor like 10x, or max")?;
+        if !(factor > 0.0 && factor.is_finite()) {
+            anyhow::bail!("the speed has to be positive");
+        }
+        Ok(Self::Factor(factor))
+    }
+}
+
+impl Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Speed::Factor(factor) => write!(f, "{factor}x"),
+            Speed::Max => f.write_str("max"),
+        }
+    }
+}
+
+/// Parses a position in a recording like `90s`, `5m` or `1h30m`, a bare number is seconds.
+pub fn parse_offset(s: &str) -> anyhow::Result<Duration> {
+    if let Ok(secs) = s.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).context("invalid offset");
+    }
+    let mut offset = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .with_context(|| format!("missing unit after {rest}"))?;
+        let value: f64 = rest[..end]
+            .parse()
+            .with_context(|| format!("invalid offset {s}"))?;
+        let unit_end = rest[end..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |i| end + i);
+        let unit = match &rest[end..unit_end] {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            unit => anyhow::bail!("unknown unit {unit}, expected ms, s, m or h"),
+        };
+        offset += Duration::try_from_secs_f64(value * unit).context("invalid offset")?;
+        rest = &rest[unit_end..];
+    }
+    Ok(offset)
+}
+
+/// Replays a recording made with `sysrat record`, paced like it was recorded.
+pub struct RecordingSource {
+    recording: Recording<BufReader<File>>,
+    speed: Speed,
+    /// Events before this are replayed at once, to build the state at that point.
+    seek: Duration,
+    /// When the event at `seek` was replayed.
+    started: Option<Instant>,
+}
+
+impl RecordingSource {
+    pub fn new(path: &Path, speed: Speed, seek: Duration) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let recording = Recording::new(BufReader::new(file))
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(Self {
+            recording,
+            speed,
+            seek,
+            started: None,
+        })
+    }
+}
+
+impl EventSource for RecordingSource {
+    fn next_event(&mut self) -> anyhow::Result<Option<SourceEvent>> {
+        let Some((at, event)) = self.recording.next_event()? else {
+            return Ok(None);
+        };
+        if let (Some(elapsed), Speed::Factor(factor)) = (at.checked_sub(self.seek), self.speed) {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            let due = started + elapsed.div_f64(factor);
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        Ok(Some(match event {
+            RecordedEvent::Exec(process) => SourceEvent::Exec(process),
+            RecordedEvent::Exit(exit) => SourceEvent::Exit(exit),
+            RecordedEvent::Fork(fork) => SourceEvent::Fork(fork),
+            RecordedEvent::Enriched {
+                key,
+                timestamp,
+                snapshot,
+            } => SourceEvent::Enriched {
+                key,
+                timestamp,
+                snapshot,
+            },
+            RecordedEvent::Dropped { total, timestamp } => {
+                SourceEvent::Dropped { total, timestamp }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_speed_and_offset() {
+        assert_eq!("max".parse::<Speed>().unwrap(), Speed::Max);
+        assert_eq!("10x".parse::<Speed>().unwrap(), Speed::Factor(10.0));
+        assert_eq!("0.5".parse::<Speed>().unwrap(), Speed::Factor(0.5));
+        assert!("0".parse::<Speed>().is_err());
+        assert!("fast".parse::<Speed>().is_err());
+
+        assert_eq!(parse_offset("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_offset("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_offset("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_offset("250ms").unwrap(), Duration::from_millis(250));
+        assert!(parse_offset("5d").is_err());
+        assert!(parse_offset("m").is_err());
+    }
+}
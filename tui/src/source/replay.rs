@@ -0,0 +1,37 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Lines},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+
+use super::{bpftrace, EventSource, SourceEvent};
+use crate::process_service::ProcessService;
+
+/// Replays `execsnoop.bpf` output previously captured to a file, e.g. with
+/// `bpftrace -f json -q execsnoop.bpf > capture.jsonl`.
+pub struct ReplaySource {
+    lines: Lines<BufReader<File>>,
+    parser: bpftrace::Parser,
+    process_service: ProcessService,
+}
+
+impl ReplaySource {
+    /// Opens the capture at `path`.
+    pub fn new(path: PathBuf) -> anyhow::Result<Self> {
+        let file =
+            File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+        Ok(Self {
+            lines: BufReader::new(file).lines(),
+            parser: bpftrace::Parser::default(),
+            process_service: ProcessService::new(),
+        })
+    }
+}
+
+impl EventSource for ReplaySource {
+    fn next_event(&mut self) -> anyhow::Result<Option<SourceEvent>> {
+        bpftrace::next_event(&mut self.lines, &mut self.parser, &self.process_service)
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{ExecKey, ProcessExecution, ProcessKey};
+
+/// A row of the tree view.
+#[derive(Debug, Clone)]
+pub struct TreeNode<'a> {
+    pub process: &'a ProcessExecution,
+    /// Where the process was among the ones flattened, or else the key of the synthetic parent it
+    /// is: not an exec that was seen, but a parent that was already running, read from `/proc`.
+    pub position: Result<usize, ProcessKey>,
+    pub depth: usize,
+    pub has_children: bool,
+    pub collapsed: bool,
+}
+
+/// Nests `processes` under the row of their parent, depth first.
+///
+/// The parent of a row is the latest earlier row of its parent's [`ProcessKey`], or else the entry
+/// of `synthetic_parents` for that key. Rows with neither are roots. The children of the nodes in
+/// `collapsed` are left out.
+pub fn flatten<'a>(
+    processes: impl IntoIterator<Item = &'a ProcessExecution>,
+    synthetic_parents: &'a HashMap<ProcessKey, ProcessExecution>,
+    collapsed: &HashSet<ExecKey>,
+) -> Vec<TreeNode<'a>> {
+    let mut nodes: Vec<(&ProcessExecution, Result<usize, ProcessKey>)> = Vec::new();
+    let mut children: Vec<Vec<usize>> = Vec::new();
+    let mut roots = Vec::new();
+    let mut latest_by_pid: HashMap<u32, usize> = HashMap::new();
+    let mut synthetic_by_key = HashMap::new();
+
+    for (position, process) in processes.into_iter().enumerate() {
+        let index = nodes.len();
+        nodes.push((process, Ok(position)));
+        children.push(Vec::new());
+
+        let parent_key = process.parent_key();
+        let parent = latest_by_pid.get(&process.ppid).copied().filter(|&i| {
+            let (parent, _) = nodes[i];
+            parent.key().matches(parent_key)
+        });
+        let parent = parent.or_else(|| {
+            let parent = synthetic_parents.get(&parent_key)?;
+            Some(*synthetic_by_key.entry(parent_key).or_insert_with(|| {
+                nodes.push((parent, Err(parent_key)));
+                children.push(Vec::new());
+                roots.push(nodes.len() - 1);
+                nodes.len() - 1
+            }))
+        });
+        match parent {
+            Some(parent) => children[parent].push(index),
+            None => roots.push(index),
+        }
+        latest_by_pid.insert(process.pid, index);
+    }
+
+    let mut rows = Vec::with_capacity(nodes.len());
+    let mut stack = roots.into_iter().rev().map(|i| (i, 0)).collect::<Vec<_>>();
+    while let Some((index, depth)) = stack.pop() {
+        let (process, position) = nodes[index];
+        let is_collapsed = collapsed.contains(&process.exec_key());
+        rows.push(TreeNode {
+            process,
+            position,
+            depth,
+            has_children: !children[index].is_empty(),
+            collapsed: is_collapsed,
+        });
+        if !is_collapsed {
+            stack.extend(children[index].iter().rev().map(|&i| (i, depth + 1)));
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, ppid: u32, args: &str) -> ProcessExecution {
+        ProcessExecution {
+            pid,
+            start_time: Some(u64::from(pid)),
+            ppid,
+            parent_start_time: Some(u64::from(ppid)),
+            args: args.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_flatten() {
+        let processes = [
+            process(20, 10, "make"),
+            process(30, 1, "top"),
+            process(21, 20, "cc -c main.c"),
+            process(22, 20, "cc -c util.c"),
+            process(23, 21, "as"),
+            // Forked by an earlier process with the pid of make.
+            ProcessExecution {
+                parent_start_time: Some(1),
+                ..process(24, 20, "ld")
+            },
+        ];
+        let bash = process(10, 1, "bash");
+        let synthetic_parents = HashMap::from([(bash.key(), bash.clone())]);
+
+        let rows = flatten(&processes, &synthetic_parents, &HashSet::new());
+        let tree = rows
+            .iter()
+            .map(|n| (n.process.args.as_str(), n.depth, n.position.is_err()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tree,
+            [
+                ("bash", 0, true),
+                ("make", 1, false),
+                ("cc -c main.c", 2, false),
+                ("as", 3, false),
+                ("cc -c util.c", 2, false),
+                ("top", 0, false),
+                ("ld", 0, false),
+            ]
+        );
+        assert_eq!(rows[0].position, Err(bash.key()));
+        assert_eq!(rows[3].position, Ok(4));
+
+        let collapsed = HashSet::from([processes[0].exec_key()]);
+        let rows = flatten(&processes, &synthetic_parents, &collapsed);
+        assert_eq!(rows.len(), 4);
+        assert!(rows[1].collapsed && rows[1].has_children);
+    }
+}
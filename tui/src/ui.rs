@@ -1,31 +1,27 @@
-use std::borrow::BorrowMut;
-
-use color_eyre::owo_colors::OwoColorize;
 use ratatui::{
     buffer::Buffer,
-    layout::{Alignment, Layout, Rect},
-    style::{Color, Stylize},
+    layout::{Layout, Rect},
+    style::Color,
     widgets::{Block, BorderType, StatefulWidget, Widget},
 };
 
 use ratatui::{
-    DefaultTerminal, Frame,
-    crossterm::event::{self, Event, KeyCode, KeyEventKind},
-    layout::{Constraint, Margin},
-    style::{self, Modifier, Style},
-    text::Text,
+    layout::{Constraint, Flex, Margin},
+    style::{Modifier, Style},
+    text::Span,
+    text::{Line, Text},
     widgets::{
-        Cell, HighlightSpacing, Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState,
-        Table,
+        Cell, Clear, HighlightSpacing, Padding, Paragraph, Row, Scrollbar, ScrollbarOrientation,
+        Table, TableState, Wrap,
     },
 };
-use style::palette::tailwind;
-use unicode_width::UnicodeWidthStr;
-
-use crate::app::App;
+use std::time::Instant;
 
-const INFO_TEXT: [&str; 1] =
-    ["(Esc) quit | (↑) move up | (↓) move down | (←) move left | (→) move right"];
+use crate::{
+    app::{constraint_len_calculator, App, BackendStatus, TableRow},
+    columns::{Column, TimeDisplay},
+    detail::ProcessDetail,
+};
 
 const COLORS: [ratatui::prelude::Color; 12] = [
     Color::Red,
@@ -42,6 +38,12 @@ const COLORS: [ratatui::prelude::Color; 12] = [
     Color::LightRed,
 ];
 
+/// Room for what group rows show in addition to the args: `▸ 123× ` and `, first seen <time>`.
+const GROUP_LABEL_LEN: u16 = 34;
+
+/// Room for the indentation and markers of a few levels of the tree view.
+const TREE_LABEL_LEN: u16 = 16;
+
 impl App {
     fn render_table(&self, area: Rect, buf: &mut Buffer) {
         let header_style = Style::default();
@@ -49,61 +51,150 @@ impl App {
         let selected_col_style = Style::default();
         let selected_cell_style = Style::default().add_modifier(Modifier::REVERSED);
 
-        let header = ["#", "timestamp", "user", "pid", "ppid", "comm", "args"]
-            .into_iter()
+        let columns = self.columns.visible().collect::<Vec<_>>();
+        let header = std::iter::once("#".to_string())
+            .chain(columns.iter().map(|&column| {
+                let name = match (column, self.time_display) {
+                    (Column::Timestamp, TimeDisplay::Relative) => "since first".to_string(),
+                    (Column::Timestamp, TimeDisplay::Delta) => "since previous".to_string(),
+                    _ => column.to_string(),
+                };
+                match self.sort {
+                    Some(sort) if sort.column == column => {
+                        format!("{name} {}", if sort.descending { "▼" } else { "▲" })
+                    }
+                    _ => name,
+                }
+            }))
             .map(Cell::from)
             .collect::<Row>()
             .style(header_style)
             .height(1);
-        let rows = self.processes.iter().enumerate().map(|(i, data)| {
-            let item = std::iter::once(i.to_string())
-                .chain(data.ref_array().iter().cloned())
-                .collect::<Vec<_>>();
-
-
-            item.into_iter().enumerate()
-                .map(|(i, content)| {
-                    let cell = Cell::from(Text::from(format!("{content}\n")));
-                    if i == 4 {
-                        let color = content.parse::<u32>().unwrap_or(0) % COLORS.len() as u32;
-                        cell.style(Style::default().fg(COLORS[color as usize]))
-                    } else {
-                        cell
-                    }
-                })
+        // Only the rows on screen are turned into cells, the history can be long.
+        let row_count = self.row_count();
+        let height = area.height.saturating_sub(1) as usize;
+        let mut table_state = self.state.borrow_mut();
+        let selected = table_state.selected();
+        let mut offset = table_state.offset().min(row_count.saturating_sub(height));
+        if let Some(selected) = selected {
+            if selected < offset {
+                offset = selected;
+            } else if selected >= offset + height {
+                offset = selected + 1 - height;
+            }
+        }
+        *table_state.offset_mut() = offset;
+        // The row above the first one on screen, for the delta of the latter.
+        let mut previous = offset
+            .checked_sub(1)
+            .and_then(|i| self.table_row(i))
+            .map(|row| row.process().timestamp);
+        let table_rows = (offset..row_count.min(offset + height))
+            .filter_map(|i| self.table_row(i))
+            .collect::<Vec<_>>();
+        let lens = constraint_len_calculator(
+            &table_rows
+                .iter()
+                .map(|row| row.process())
+                .collect::<Vec<_>>(),
+        );
+        let index_len = (offset + table_rows.len()).to_string().len() as u16;
+
+        let rows = table_rows.into_iter().enumerate().map(|(i, row)| {
+            let i = offset + i;
+            let data = row.process();
+            let mut fields = data.ref_array();
+            fields[0] = self
+                .time_display
+                .format(data.timestamp, self.first_timestamp, previous);
+            previous = Some(data.timestamp);
+            let args = &mut fields[8];
+            match &row {
+                TableRow::Process(_) => {}
+                TableRow::Group { group, expanded } => {
+                    *args = format!(
+                        "{} {}× {args}, first seen {}",
+                        if *expanded { "▾" } else { "▸" },
+                        group.count(),
+                        group.first_seen().format("%H:%M:%S%.3f"),
+                    );
+                }
+                TableRow::Member(_) => *args = format!("  └ {args}"),
+                TableRow::Node(node) => {
+                    let marker = match (node.has_children, node.collapsed) {
+                        (false, _) => " ",
+                        (true, false) => "▾",
+                        (true, true) => "▸",
+                    };
+                    *args = format!("{}{marker} {args}", "  ".repeat(node.depth));
+                }
+            }
+            let synthetic = matches!(&row, TableRow::Node(node) if node.position.is_err());
+            let cells = columns.iter().map(|&column| {
+                let content = column.value(data, &fields);
+                let cell = Cell::from(Text::from(format!("{content}\n")));
+                if column == Column::Ppid {
+                    let color = data.ppid % COLORS.len() as u32;
+                    cell.style(Style::default().fg(COLORS[color as usize]))
+                } else {
+                    cell
+                }
+            });
+            std::iter::once(Cell::from(i.to_string()))
+                .chain(cells)
                 .collect::<Row>()
-                .style(Style::new())
+                .style(if synthetic {
+                    Style::new().fg(Color::DarkGray)
+                } else if data.failed() {
+                    Style::new().fg(Color::Red)
+                } else if data.exited_abnormally() {
+                    Style::new().fg(Color::Yellow)
+                } else {
+                    Style::new()
+                })
                 .height(1)
-
         });
+        let label_len = if self.grouping {
+            GROUP_LABEL_LEN
+        } else if self.tree_view {
+            TREE_LABEL_LEN
+        } else {
+            0
+        };
         let bar = " █ ";
-        let t = Table::new(
-            rows,
-            [
-                // + 1 is for padding.
-                Constraint::Length(2), // #
-                Constraint::Length(self.longest_item_lens.timestamp + 1),
-                Constraint::Length(self.longest_item_lens.username + 1),
-                Constraint::Length(self.longest_item_lens.pid + 1),
-                Constraint::Length(self.longest_item_lens.ppid + 1),
-                Constraint::Length(self.longest_item_lens.comm + 1),
-                Constraint::Max(self.longest_item_lens.args)
-            ],
-        )
-        .header(header)
-        .row_highlight_style(selected_row_style)
-        .column_highlight_style(selected_col_style)
-        .cell_highlight_style(selected_cell_style)
-        .highlight_symbol(Text::from(vec![
-            "".into(),
-            bar.into(),
-            bar.into(),
-            "".into(),
-        ]))
-        .highlight_spacing(HighlightSpacing::Always);
-        let mut table_state = self.state.borrow_mut();
+        let widths =
+            std::iter::once(Constraint::Length(index_len)).chain(columns.iter().map(|&column| {
+                // Room for the sort marker next to the name.
+                let len = match self.sort {
+                    Some(sort) if sort.column == column => {
+                        lens.get(column).max(column.name().len() as u16 + 2)
+                    }
+                    _ => lens.get(column),
+                };
+                match column {
+                    Column::Args => Constraint::Max(len + label_len),
+                    // + 1 is for padding.
+                    _ => Constraint::Length(len + 1),
+                }
+            }));
+        let t = Table::new(rows, widths)
+            .header(header)
+            .row_highlight_style(selected_row_style)
+            .column_highlight_style(selected_col_style)
+            .cell_highlight_style(selected_cell_style)
+            .highlight_symbol(Text::from(vec![
+                "".into(),
+                bar.into(),
+                bar.into(),
+                "".into(),
+            ]))
+            .highlight_spacing(HighlightSpacing::Always);
+        // The rows start at the offset already.
+        let mut slice_state = TableState::default()
+            .with_selected(selected.map(|selected| selected - offset))
+            .with_selected_column(table_state.selected_column());
 
-        StatefulWidget::render(t, area, buf, &mut table_state);
+        StatefulWidget::render(t, area, buf, &mut slice_state);
     }
 
     fn render_scrollbar(&self, area: Rect, buf: &mut Buffer) {
@@ -121,18 +212,174 @@ impl App {
         )
     }
 
+    fn render_filter_bar(&self, area: Rect, buf: &mut Buffer) {
+        let input = match &self.filter_input {
+            Some(input) => format!("/{input}█"),
+            None => format!(
+                "/{}",
+                self.filter
+                    .as_ref()
+                    .map(|f| f.to_string())
+                    .unwrap_or_default()
+            ),
+        };
+        let status = match &self.filter_error {
+            Some(error) => Line::from(error.lines().last().unwrap_or_default().to_string())
+                .style(Style::default().fg(Color::Red)),
+            None => Line::from(format!(
+                "{}/{} matches",
+                self.visible_count(),
+                self.processes.len()
+            )),
+        };
+        let [input_area, status_area] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Length(status.width() as u16),
+        ])
+        .areas(area);
+        Widget::render(Line::from(input), input_area, buf);
+        Widget::render(status, status_area, buf);
+    }
+
+    fn render_detail(&self, detail: &ProcessDetail, area: Rect, buf: &mut Buffer) {
+        let label_style = Style::new().add_modifier(Modifier::BOLD);
+        let label_len = detail
+            .fields
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        let mut lines = Vec::new();
+        for (label, value) in &detail.fields {
+            for (i, value_line) in value.lines().enumerate() {
+                let label = if i == 0 { *label } else { "" };
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{label:>label_len$}  "), label_style),
+                    Span::raw(value_line.to_string()),
+                ]));
+            }
+        }
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .padding(Padding::horizontal(1))
+            .title_top(Line::from(format!(" {} ", detail.title)))
+            .title_bottom(Line::from(" j/k scroll, Enter/Esc close ").right_aligned());
+        let pane = Paragraph::new(lines)
+            .block(block)
+            .scroll((self.detail_scroll, 0));
+        Widget::render(Clear, area, buf);
+        Widget::render(pane, area, buf);
+    }
+
+    fn render_column_chooser(&self, cursor: usize, area: Rect, buf: &mut Buffer) {
+        let lines = self
+            .columns
+            .all()
+            .iter()
+            .enumerate()
+            .map(|(i, (column, visible))| {
+                let line = Line::from(format!("[{}] {column}", if *visible { "x" } else { " " }));
+                if i == cursor {
+                    line.style(Style::new().add_modifier(Modifier::REVERSED))
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>();
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .padding(Padding::horizontal(1))
+            .title_top(Line::from(" columns "))
+            .title_bottom(Line::from(" space show/hide, J/K move, Esc close "));
+        Widget::render(Clear, area, buf);
+        Widget::render(Paragraph::new(lines).block(block), area, buf);
+    }
+
     fn render_footer(&self, area: Rect, buf: &mut Buffer) {
-        let text = format!("{}", self.debug_message);
-        let info_footer = Paragraph::new(Text::from(text))
+        let mut block = Block::bordered()
+            .border_type(BorderType::Plain)
+            .border_style(Style::new())
+            .title_top(self.backend_status_line());
+        if !self.kernel_filters.is_empty() {
+            block = block.title_top(Line::from(format!(
+                " kernel filters: {} ",
+                self.kernel_filters
+            )));
+        }
+        if self.paused {
+            block = block.title_top(
+                Line::from(format!(
+                    " paused, {} new events, p to resume ",
+                    self.backlog_new_processes
+                ))
+                .style(Style::default().fg(Color::Yellow))
+                .right_aligned(),
+            );
+        }
+        block = block.title_bottom(Line::from(if self.follow_tail {
+            " following new rows, f to stop ".to_string()
+        } else {
+            format!(" {} rows, End to follow ", self.processes.len())
+        }));
+        if self.dropped_events > 0 {
+            block = block.title_bottom(
+                Line::from(format!(" {} events dropped ", self.dropped_events))
+                    .style(Style::default().fg(Color::Red))
+                    .right_aligned(),
+            );
+        }
+        if self.discarded_events > 0 {
+            block = block.title_bottom(
+                Line::from(format!(
+                    " {} events discarded while paused ",
+                    self.discarded_events
+                ))
+                .style(Style::default().fg(Color::Yellow))
+                .right_aligned(),
+            );
+        }
+        if self.malformed_lines > 0 {
+            block = block.title_bottom(
+                Line::from(match self.malformed_lines {
+                    1 => " 1 malformed line ".to_string(),
+                    n => format!(" {n} malformed lines "),
+                })
+                .style(Style::default().fg(Color::Red))
+                .right_aligned(),
+            );
+        }
+        let info_footer = Paragraph::new(Text::from(self.debug_message.as_str()))
             .style(Style::new())
             .centered()
-            .block(
-                Block::bordered()
-                    .border_type(BorderType::Plain)
-                    .border_style(Style::new()),
-            );
+            .wrap(Wrap { trim: true })
+            .block(block);
         Widget::render(info_footer, area, buf);
     }
+
+    fn backend_status_line(&self) -> Line<'static> {
+        let (text, color) = match self.backend_status {
+            BackendStatus::Starting => (" starting ".to_string(), Color::Yellow),
+            BackendStatus::Running(backend) => (format!(" {backend} "), Color::Green),
+            BackendStatus::Exited(backend) => (format!(" {backend} exited "), Color::Yellow),
+            BackendStatus::Failed(backend) => (format!(" {backend} failed "), Color::Red),
+            BackendStatus::Restarting {
+                backend,
+                attempt,
+                at,
+            } => {
+                let secs = at.saturating_duration_since(Instant::now()).as_secs_f64();
+                (
+                    format!(
+                        " restarting {backend} in {}s, attempt {attempt} ",
+                        secs.ceil()
+                    ),
+                    Color::Yellow,
+                )
+            }
+        };
+        Line::from(text).style(Style::default().fg(color))
+    }
 }
 
 impl Widget for &App {
@@ -143,7 +390,16 @@ impl Widget for &App {
     // - https://docs.rs/ratatui/latest/ratatui/widgets/index.html
     // - https://github.com/ratatui/ratatui/tree/master/examples
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let vertical = &Layout::vertical([Constraint::Min(5), Constraint::Length(4)]);
+        let filter_bar_height = if self.filter_input.is_some() || self.filter.is_some() {
+            1
+        } else {
+            0
+        };
+        let vertical = &Layout::vertical([
+            Constraint::Min(5),
+            Constraint::Length(filter_bar_height),
+            Constraint::Length(4),
+        ]);
         let horizontal = Layout::horizontal([Constraint::Fill(1), Constraint::Max(3)]);
 
         let rects = vertical.split(area);
@@ -152,6 +408,28 @@ impl Widget for &App {
 
         self.render_table(table, buf);
         self.render_scrollbar(scrollbar, buf);
-        self.render_footer(rects[1], buf);
+        if filter_bar_height > 0 {
+            self.render_filter_bar(rects[1], buf);
+        }
+        self.render_footer(rects[2], buf);
+
+        if let Some(cursor) = self.column_chooser {
+            let [popup] = Layout::horizontal([Constraint::Length(42)])
+                .flex(Flex::Center)
+                .areas(area);
+            let [popup] =
+                Layout::vertical([Constraint::Length(self.columns.all().len() as u16 + 2)])
+                    .flex(Flex::Center)
+                    .areas(popup);
+            self.render_column_chooser(cursor, popup, buf);
+        }
+
+        if let Some(detail) = &self.detail {
+            let popup = area.inner(Margin {
+                vertical: area.height / 10,
+                horizontal: area.width / 10,
+            });
+            self.render_detail(detail, popup, buf);
+        }
     }
 }